@@ -0,0 +1,9 @@
+% 与math.json相同的知识库
+leq(zero, three).
+leq(seven, nine).
+leq(X, add(X, zero)).
+leq(add(X, zero), X).
+leq(X, Z) :- leq(X, Y), leq(Y, Z).
+leq(add(W, X), add(Y, Z)) :- leq(W, Y), leq(X, Z).
+leq(X, X).
+leq(add(X, Y), add(Y, X)).
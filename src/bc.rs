@@ -1,188 +1,1277 @@
-use super::{Atom, KB, ReasoningError, Rule, Theta};
-use crate::unify::{exhaust_subst, unify};
-
-/// 反向链接推理器
-pub fn bc(kb: &KB, theorem: &Atom, verbose: bool, max_depth: usize) -> Result<(), ReasoningError> {
-    let mut thetas = Vec::<Theta>::new();
-    let mut call_time = 0;
-    let wrapped_theorem = vec![theorem.clone()];
-    let mut call_stack = Vec::<Atom>::new();
-    let mut known_facts = Vec::<Atom>::new();
-    for rule in kb.rules.iter() {
-        if rule.is_fact() {
-            known_facts.push(rule.conclusion.clone());
-        }
-    }
-    let proof = bc_core(
-        kb,
-        &wrapped_theorem,
-        &mut thetas,
-        verbose,
-        &mut call_time,
-        &mut call_stack,
-        0,
-        max_depth,
-        &mut known_facts,
-    );
-    if verbose {
-        println!("证明步数：{call_time}");
-    }
-    proof
-}
-
-/// 暂存发现的子命题
-struct Ckpt {
-    theorems: Vec<Atom>,
-    thetas: Vec<Theta>,
-}
-
-/// 对于一条命题，找到所有能与其合一的规则结论，记录结论需要的条件和使用的替换
-fn get_prove_path(
-    rules: &[Rule],
-    theorem: &Atom,
-    thetas: &[Theta],
-) -> Result<Vec<Ckpt>, ReasoningError> {
-    let mut to_prove_list = Vec::<Ckpt>::new();
-    for rule in rules.iter() {
-        let mut tmp_thetas: Vec<Theta> = thetas.to_owned();
-        if unify(theorem, &rule.conclusion, &mut tmp_thetas).is_ok() {
-            to_prove_list.push(Ckpt {
-                theorems: rule.condition.clone(),
-                thetas: tmp_thetas,
-            });
+use super::{Atom, KB, Literal, PredicateIndicator, ReasoningError, Rule, Symbol, Theta, var};
+use crate::bindings::Bindings;
+use crate::datalog::{Database, is_datalog};
+use crate::facts::FactStore;
+use crate::index::RuleIndex;
+use crate::magic::magic_sets;
+use crate::proof::ProofTree;
+use crate::unify::{exhaust_subst, exhaust_subst_symbol, is_variant, unify};
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Arguments, Display};
+use std::ops::ControlFlow;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// ## 搜索参数
+#[derive(Debug, Clone)]
+pub struct SearchConfig {
+    /// 最大推理深度，超过该深度的分支被剪去。迭代加深时为深度上限
+    pub max_depth: usize,
+    /// 最大推理步数，即最多尝试展开多少次命题，达到后终止搜索
+    pub max_steps: Option<usize>,
+    /// 搜索时间上限，超时后终止搜索
+    pub timeout: Option<Duration>,
+    /// 搜索策略
+    pub strategy: Strategy,
+    /// 合一时进行出现检查。关闭后推理更快，但可能得到不正确的证明或陷入无限递归
+    pub occurs_check: bool,
+    /// 显示详细证明过程
+    pub verbose: bool,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        SearchConfig {
+            max_depth: 5,
+            max_steps: None,
+            timeout: None,
+            strategy: Strategy::DepthFirst,
+            occurs_check: true,
+            verbose: false,
         }
     }
-    if to_prove_list.is_empty() {
-        Err(ReasoningError::ProofNotFound)
-    } else {
-        Ok(to_prove_list)
+}
+
+/// ## 搜索策略
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Strategy {
+    /// 在`max_depth`以内进行深度优先搜索
+    #[default]
+    DepthFirst,
+    /// 迭代加深：从深度0开始逐步放宽深度限制，直至找到证明、
+    /// 搜索不再因深度被剪枝、步数或时间用尽或达到`max_depth`
+    IterativeDeepening,
+    /// 知识库为Datalog程序（不含函数符号且满足值域限制）时，按待证命题做魔集改写，
+    /// 自底向上求出与之相关的全部事实后查表回答，结果总是完整的；
+    /// 否则或需要构造证明树时，按`DepthFirst`搜索
+    Datalog,
+}
+
+/// ## 搜索中触发的限制
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    /// 有分支因超过最大推理深度被剪去，搜索仍会继续
+    Depth,
+    /// 推理步数达到上限，搜索终止
+    Steps,
+    /// 搜索超时，搜索终止
+    Timeout,
+    /// 有含变量的命题因与祖先命题互为变体（循环论证）被剪去，可能遗漏了部分解，搜索仍会继续。
+    /// 放宽限制无济于事，可为相应谓词声明表格化求解
+    Cycle,
+}
+
+impl Display for Limit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        ReasoningError::from(*self).fmt(f)
+    }
+}
+
+impl From<Limit> for ReasoningError {
+    fn from(value: Limit) -> Self {
+        match value {
+            Limit::Depth => ReasoningError::DepthLimitExceed,
+            Limit::Steps => ReasoningError::StepLimitExceed,
+            Limit::Timeout => ReasoningError::Timeout,
+            Limit::Cycle => ReasoningError::CycleProof,
+        }
     }
 }
 
-/// ## 证明一系列关联命题的反向链接算法
-/// 其中每条命题的前提包含排在其之前的所有命题
-/// 如：为证明0<9，找到的一条可行路径需要证明存在x使得0<x且x<9
-/// 因此需要对命题序列0<x,x<9调用该函数
-/// 函数在通过找到x=x_0证明0<x后不立刻认为0<x得证
-/// 而是将x=x_0代入x<9
-/// 如果x_0不满足x<9则认为证明失败，算法回退采取其他可行路径证明0<9
-/// 当然如果x_0满足了x<9证明就成功了。
-fn bc_core(
+/// ## 证明结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// 命题得证
+    Proved,
+    /// 搜索未触发任何限制，穷尽所有证明路径后命题仍无法得证
+    Disproved,
+    /// 未找到证明，但搜索因触发限制而不完整，放宽限制后可能得证
+    Unknown(Limit),
+    /// 未找到证明，且有否定因被否定的命题含有未确定的变量而无法求值，
+    /// 须调整条件的顺序使变量在否定之前确定，放宽限制无济于事
+    Floundered,
+}
+
+/// ## 一次搜索的概况
+#[derive(Debug, Clone, Copy)]
+pub struct SearchSummary {
+    /// 找到的不重复的解的个数
+    pub solutions: usize,
+    /// 搜索中触发的限制。步数与时间限制优先于深度限制报告
+    pub limit: Option<Limit>,
+    /// 是否有分支因否定中含有未确定的变量而失败，此时可能遗漏了部分解
+    pub floundered: bool,
+    /// 证明结果
+    pub outcome: Outcome,
+}
+
+impl SearchSummary {
+    fn new(solutions: usize, limit: Option<Limit>, floundered: bool) -> Self {
+        let outcome = match (solutions, limit) {
+            (0, _) if floundered => Outcome::Floundered,
+            (0, None) => Outcome::Disproved,
+            (0, Some(limit)) => Outcome::Unknown(limit),
+            _ => Outcome::Proved,
+        };
+        SearchSummary {
+            solutions,
+            limit,
+            floundered,
+            outcome,
+        }
+    }
+
+    /// 未找到证明时对应的错误
+    fn error(&self) -> ReasoningError {
+        match self.outcome {
+            Outcome::Unknown(limit) => limit.into(),
+            Outcome::Floundered => ReasoningError::Floundering,
+            _ => ReasoningError::ProofNotFound,
+        }
+    }
+}
+
+/// ## 反向链接推理器
+/// 证明成功时返回待证命题中每个变量的绑定。
+/// 未找到证明时，若搜索因触发限制而不完整则返回对应的错误，否则返回`ProofNotFound`
+pub fn bc(kb: &KB, theorem: &Atom, config: &SearchConfig) -> Result<Vec<Theta>, ReasoningError> {
+    let mut answer = None;
+    let summary = bc_solutions(kb, theorem, config, |solution| {
+        answer = Some(solution);
+        ControlFlow::Break(())
+    });
+    answer.ok_or_else(|| summary.error())
+}
+
+/// ## 构造证明树的反向链接推理器
+/// 证明成功时返回待证命题中每个变量的绑定以及对应的证明树
+pub fn bc_proof(
     kb: &KB,
-    theorems: &[Atom],
-    thetas: &mut Vec<Theta>,
-    verbose: bool,
-    call_time: &mut usize,
-    call_stack: &mut Vec<Atom>,
-    depth: usize,
-    max_depth: usize,
-    facts: &mut Vec<Atom>,
-) -> Result<(), ReasoningError> {
-    if theorems.is_empty() {
-        return Ok(());
-    }
-    let head = &theorems[0];
-    let rest = &theorems[1..];
-    let subst_theorem = exhaust_subst(head, thetas);
-    if facts.contains(&subst_theorem) {
-        return bc_core(
-            kb, rest, thetas, verbose, call_time, call_stack, depth, max_depth, facts,
-        );
+    theorem: &Atom,
+    config: &SearchConfig,
+) -> Result<(Vec<Theta>, ProofTree), ReasoningError> {
+    let mut answer = None;
+    let summary = bc_proofs(kb, theorem, config, |solution, proof| {
+        answer = Some((solution, proof));
+        ControlFlow::Break(())
+    });
+    answer.ok_or_else(|| summary.error())
+}
+
+/// ## 按搜索顺序枚举待证命题的所有解
+/// 每找到一个与此前不同的解，就以该解中变量的绑定调用一次`on_solution`。
+/// 回调返回`ControlFlow::Break`时停止搜索，不再计算后续的解。
+pub fn bc_solutions(
+    kb: &KB,
+    theorem: &Atom,
+    config: &SearchConfig,
+    mut on_solution: impl FnMut(Vec<Theta>) -> ControlFlow<()>,
+) -> SearchSummary {
+    search(kb, theorem, config, false, |solution, _| {
+        on_solution(solution)
+    })
+}
+
+/// ## 按搜索顺序枚举待证命题的所有解及其证明树
+/// 与`bc_solutions`相同，但回调同时得到该解的证明树
+pub fn bc_proofs(
+    kb: &KB,
+    theorem: &Atom,
+    config: &SearchConfig,
+    mut on_solution: impl FnMut(Vec<Theta>, ProofTree) -> ControlFlow<()>,
+) -> SearchSummary {
+    search(kb, theorem, config, true, |solution, proof| {
+        on_solution(solution, proof.expect("构造证明树时每个解都有证明"))
+    })
+}
+
+/// 枚举不重复的解，`build_tree`为真时同时给出每个解的证明树。
+/// 迭代加深时各个解按证明所需的深度由浅到深给出
+fn search(
+    kb: &KB,
+    theorem: &Atom,
+    config: &SearchConfig,
+    build_tree: bool,
+    mut on_solution: impl FnMut(Vec<Theta>, Option<ProofTree>) -> ControlFlow<()>,
+) -> SearchSummary {
+    let mut solutions = Solutions::new(kb, theorem, config, build_tree);
+    for solution in solutions.by_ref() {
+        if on_solution(solution.bindings, solution.proof).is_break() {
+            break;
+        }
+    }
+    solutions.trace_steps();
+    solutions.summary()
+}
+
+/// ## 待证命题的一个解
+#[derive(Debug, Clone)]
+pub struct Solution {
+    /// 待证命题中变量的绑定
+    pub bindings: Vec<Theta>,
+    /// 该解的证明树，仅在要求构造证明树时给出
+    pub proof: Option<ProofTree>,
+}
+
+/// ## 可暂停的解枚举器
+/// 每次调用`next`时从上一个解回退，继续搜索直至找到下一个与此前不同的解。
+/// 两次调用之间的搜索状态完整保存在枚举器中，可随时暂停或放弃搜索
+pub struct Solutions<'a> {
+    source: Source<'a>,
+    theorem: Atom,
+    /// 已给出的解
+    found: HashSet<Vec<Theta>>,
+    /// 是否已不会再有新的解
+    done: bool,
+    /// 收集到的证明过程，为None时直接输出
+    trace: Option<Vec<String>>,
+}
+
+/// 解的来源
+enum Source<'a> {
+    /// 反向链接搜索
+    Search(Box<Search<'a>>),
+    /// 自底向上求值后查表得到的全部解
+    Materialized(std::vec::IntoIter<Vec<Theta>>),
+}
+
+impl<'a> Solutions<'a> {
+    /// `build_tree`为真时每个解都附带其证明树
+    pub(crate) fn new(
+        kb: &'a KB,
+        theorem: &Atom,
+        config: &'a SearchConfig,
+        build_tree: bool,
+    ) -> Self {
+        Solutions::with_trace(kb, theorem, config, build_tree, None)
     }
-    if call_stack.contains(&subst_theorem) {
-        if verbose {
-            eprintln!("证明{subst_theorem}是循环论证，回退");
+
+    /// 开启详细输出时将证明过程收集起来，由`take_trace`取出，而非直接输出
+    pub(crate) fn traced(kb: &'a KB, theorem: &Atom, config: &'a SearchConfig) -> Self {
+        Solutions::with_trace(kb, theorem, config, false, Some(Vec::new()))
+    }
+
+    fn with_trace(
+        kb: &'a KB,
+        theorem: &Atom,
+        config: &'a SearchConfig,
+        build_tree: bool,
+        mut trace: Option<Vec<String>>,
+    ) -> Self {
+        // 只需回答一个命题，按其绑定模式做魔集改写后只求出与之相关的事实
+        if config.strategy == Strategy::Datalog
+            && !build_tree
+            && is_datalog(kb)
+            && let (relevant, goal) = magic_sets(kb, theorem)
+            && let Some(database) = Database::new(&relevant)
+        {
+            if config.verbose {
+                let (rounds, facts) = (database.rounds(), database.len());
+                write_trace(
+                    &mut trace,
+                    format_args!("自底向上求值{rounds}轮，共{facts}条事实"),
+                    false,
+                );
+            }
+            let mut solutions = Solutions::materialized(theorem, database.query(&goal));
+            solutions.trace = trace;
+            return solutions;
+        }
+        let mut search = Search::new(kb, config, build_tree);
+        search.trace = trace;
+        let first_depth = if config.strategy == Strategy::IterativeDeepening {
+            0
+        } else {
+            config.max_depth
+        };
+        search.restart(theorem, first_depth);
+        Solutions {
+            source: Source::Search(Box::new(search)),
+            theorem: theorem.clone(),
+            found: HashSet::new(),
+            done: false,
+            trace: None,
         }
-        return Err(ReasoningError::CycleProof);
     }
-    if depth > max_depth {
-        if verbose {
-            eprintln!("尝试证明{subst_theorem}时深度超限，回退");
+
+    /// 依次给出已求得的全部解
+    fn materialized(theorem: &Atom, answers: Vec<Vec<Theta>>) -> Self {
+        Solutions {
+            source: Source::Materialized(answers.into_iter()),
+            theorem: theorem.clone(),
+            found: HashSet::new(),
+            done: false,
+            trace: None,
         }
-        return Err(ReasoningError::DepthLimitExceed);
     }
-    if verbose {
-        println!("对{subst_theorem}的证明：");
+
+    /// 取出目前为止收集到的证明过程
+    pub(crate) fn take_trace(&mut self) -> Vec<String> {
+        let trace = match &mut self.source {
+            Source::Search(search) => &mut search.trace,
+            Source::Materialized(_) => &mut self.trace,
+        };
+        trace.as_mut().map(std::mem::take).unwrap_or_default()
     }
-    *call_time += 1;
-    let rules: Vec<Rule> = kb
-        .rules
+
+    /// 开启详细输出时输出搜索所用的步数
+    pub(crate) fn trace_steps(&mut self) {
+        if let Source::Search(search) = &mut self.source {
+            let steps = search.call_time;
+            search.trace(format_args!("证明步数：{steps}"));
+        }
+    }
+
+    /// ## 到目前为止的搜索概况
+    pub fn summary(&self) -> SearchSummary {
+        let (limit, floundered) = match &self.source {
+            Source::Search(search) => (
+                search
+                    .aborted
+                    .or(search.depth_cutoff.then_some(Limit::Depth))
+                    .or(search.cycle_cutoff.then_some(Limit::Cycle)),
+                search.floundered,
+            ),
+            Source::Materialized(_) => (None, false),
+        };
+        SearchSummary::new(self.found.len(), limit, floundered)
+    }
+}
+
+impl Iterator for Solutions<'_> {
+    type Item = Solution;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let search = match &mut self.source {
+            Source::Search(search) => search,
+            Source::Materialized(answers) => loop {
+                let mut bindings = answers.next()?;
+                bindings.retain(|theta| !theta.origin().is_anonymous());
+                if self.found.insert(bindings.clone()) {
+                    return Some(Solution {
+                        bindings,
+                        proof: None,
+                    });
+                }
+            },
+        };
+        while !self.done {
+            if search.bc_core() {
+                let bindings = answer_thetas(&self.theorem, &search.bindings);
+                if !self.found.insert(bindings.clone()) {
+                    continue;
+                }
+                let proof = search.trees.last();
+                let proof = proof.map(|proof| proof.resolve(&search.bindings));
+                return Some(Solution { bindings, proof });
+            }
+            // 本轮搜索已结束。迭代加深时若有分支因深度被剪去，则放宽一层深度重新搜索，
+            // 沿用已得证的事实、步数与截止时间，已给出的解不再重复给出
+            if search.aborted.is_none()
+                && search.depth_cutoff
+                && search.max_depth < search.config.max_depth
+            {
+                search.restart(&self.theorem, search.max_depth + 1);
+            } else {
+                self.done = true;
+            }
+        }
+        None
+    }
+}
+
+/// 收集证明过程时记入`buffer`，否则直接输出，回退的原因输出到标准错误
+fn write_trace(buffer: &mut Option<Vec<String>>, line: Arguments, backtrack: bool) {
+    match buffer {
+        Some(buffer) => buffer.push(line.to_string()),
+        None if backtrack => eprintln!("{line}"),
+        None => println!("{line}"),
+    }
+}
+
+/// ## 由证明过程中的绑定表求出待证命题中变量的绑定
+/// 绑定结果中残留的自由变量若与待证命题中的某个变量等价，则以该变量的名称表示，
+/// 否则依次命名为`_1`、`_2`……，避免向用户暴露`Y1`之类标准化后的变量名。
+/// 匿名变量的绑定不予给出
+fn answer_thetas(theorem: &Atom, bindings: &Bindings) -> Vec<Theta> {
+    let mut vars = theorem.vars();
+    vars.retain(|v| !v.is_anonymous());
+    let resolved: Vec<Symbol> = vars
         .iter()
-        .map(|r| KB::rule_standardize(r, *call_time))
+        .map(|v| exhaust_subst_symbol(v, bindings))
         .collect();
-    if let Ok(prove_paths) = get_prove_path(&rules, &subst_theorem, thetas) {
-        for path in prove_paths {
-            let mut tmp_thetas = path.thetas.clone();
-            if bc_core(
-                kb,
-                &path.theorems,
-                &mut tmp_thetas,
-                verbose,
-                call_time,
-                call_stack,
-                depth + 1,
-                max_depth,
-                facts,
-            )
-            .is_ok()
-                && bc_core(
-                    kb,
-                    rest,
-                    &mut tmp_thetas,
-                    verbose,
-                    call_time,
-                    call_stack,
+    let mut renaming = Vec::<(Symbol, Symbol)>::new();
+    for (v, r) in vars.iter().zip(resolved.iter()) {
+        if let Symbol::Var(_) = r
+            && !renaming.iter().any(|(from, _)| from == r)
+        {
+            renaming.push((r.clone(), v.clone()));
+        }
+    }
+    let mut free_vars = Vec::<Symbol>::new();
+    for r in resolved.iter() {
+        r.collect_vars(&mut free_vars);
+    }
+    let mut anonymous = 0;
+    for free_var in free_vars {
+        if !renaming.iter().any(|(from, _)| *from == free_var) {
+            anonymous += 1;
+            renaming.push((free_var, var(format!("_{anonymous}"))));
+        }
+    }
+    vars.into_iter()
+        .zip(resolved.iter())
+        .filter_map(|(v, r)| {
+            let renamed = rename_symbol(r, &renaming);
+            (renamed != v).then(|| Theta::new(v, renamed).unwrap())
+        })
+        .collect()
+}
+
+/// 按照给定的变量对应关系对项做一次性替换
+fn rename_symbol(x: &Symbol, renaming: &[(Symbol, Symbol)]) -> Symbol {
+    match x {
+        Symbol::Var(_) => renaming
+            .iter()
+            .find(|(from, _)| from == x)
+            .map_or_else(|| x.clone(), |(_, to)| to.clone()),
+        Symbol::Val(_) => x.clone(),
+        Symbol::Func(name, args) => Symbol::Func(
+            *name,
+            args.iter()
+                .map(|arg| rename_symbol(arg, renaming))
+                .collect(),
+        ),
+    }
+}
+
+/// 待证目标
+enum Goal {
+    /// 待证命题及其所处的推理深度
+    Prove(Atom, usize),
+    /// 以有限失败证明的否定条件中被否定的命题及其所处的推理深度
+    Refute(Atom, usize),
+    /// 标记某条命题的前提已全部得证，同时记录证明它所用的规则、
+    /// 合一时新增的置换与前提个数，供构造证明树使用
+    Proved {
+        theorem: Atom,
+        rule: usize,
+        thetas: Vec<Theta>,
+        conditions: usize,
+    },
+    /// 标记表格化求值得到了命题的一个答案
+    Answer(Atom),
+}
+
+/// ## 待证目标组成的不可变链表
+/// 展开命题时只需在链表头部加入前提，各个选择点共享其余目标
+#[derive(Clone, Default)]
+struct Goals(Option<Rc<GoalNode>>);
+
+struct GoalNode {
+    goal: Goal,
+    next: Goals,
+}
+
+impl Goals {
+    /// 在链表头部加入一个目标
+    fn push(&self, goal: Goal) -> Goals {
+        Goals(Some(Rc::new(GoalNode {
+            goal,
+            next: self.clone(),
+        })))
+    }
+
+    fn split_first(&self) -> Option<(&Goal, &Goals)> {
+        self.0.as_deref().map(|node| (&node.goal, &node.next))
+    }
+}
+
+impl Drop for Goals {
+    /// 逐个释放不再共享的节点，避免很长的链表在递归释放时耗尽调用栈
+    fn drop(&mut self) {
+        let mut next = self.0.take();
+        while let Some(node) = next {
+            next = match Rc::try_unwrap(node) {
+                Ok(mut node) => node.next.0.take(),
+                Err(_) => None,
+            };
+        }
+    }
+}
+
+/// ## 选择点
+/// 回退到选择点时撤销其建立之后的所有修改，再尝试其下一个分支
+struct ChoicePoint {
+    /// 建立选择点时绑定表轨迹的位置
+    bindings: usize,
+    /// 建立选择点时撤销记录的位置
+    undo: usize,
+    branches: Branches,
+}
+
+/// 选择点尚未尝试的分支
+enum Branches {
+    /// 结论可能与命题合一的候选规则
+    Rules {
+        theorem: Atom,
+        depth: usize,
+        rules: std::vec::IntoIter<(usize, Rule)>,
+        rest: Goals,
+    },
+    /// 答案表中尚未取出的答案
+    Answers {
+        theorem: Atom,
+        answers: std::vec::IntoIter<(Atom, Option<ProofTree>)>,
+        rest: Goals,
+    },
+    /// 表格化求值的一轮。回退到此处时本轮已结束，合并新答案并判断是否达到不动点
+    Generator {
+        table: usize,
+        theorem: Atom,
+        depth: usize,
+        rest: Goals,
+        /// 本轮开始时累计的答案个数
+        before: usize,
+    },
+}
+
+impl Branches {
+    fn is_empty(&self) -> bool {
+        match self {
+            Branches::Rules { rules, .. } => rules.len() == 0,
+            Branches::Answers { answers, .. } => answers.len() == 0,
+            Branches::Generator { .. } => false,
+        }
+    }
+}
+
+/// 回退时需要撤销的修改，绑定表的修改由其自身的轨迹记录
+enum Undo {
+    /// 证明树栈压入了一个子证明
+    Tree,
+    /// 证明树栈顶的若干子证明合并为一个节点
+    Merge,
+    /// 祖先命题栈压入了一条命题
+    Call,
+    /// 祖先命题栈弹出了一条命题
+    Return(Atom),
+}
+
+/// 祖先命题的分组：谓词与入栈时已不含变量的第一个参数
+type AncestorKey = (PredicateIndicator, Option<Symbol>);
+
+fn ancestor_key(ancestor: &Atom) -> AncestorKey {
+    let first = ancestor.args.first().filter(|arg| !arg.contains_var());
+    (ancestor.indicator(), first.cloned())
+}
+
+/// 一次反向链接搜索的状态
+struct Search<'a> {
+    kb: &'a KB,
+    index: RuleIndex,
+    config: &'a SearchConfig,
+    /// 本轮搜索的深度限制
+    max_depth: usize,
+    /// 截止时间，时间上限过长以致无法表示时视为不限时
+    deadline: Option<Instant>,
+    call_time: usize,
+    /// 已分配的最大变量编号，规则标准化与答案改名时分配新编号
+    fresh: usize,
+    /// 当前证明路径上所有变量的绑定，回退时按轨迹撤销
+    bindings: Bindings,
+    /// 当前分支尚待证明的目标，为None时当前分支已失败，需要回退
+    goals: Option<Goals>,
+    /// 选择点栈，由早到晚排列
+    choices: Vec<ChoicePoint>,
+    /// 证明树栈与祖先命题栈的修改记录，回退时逆序撤销
+    undo: Vec<Undo>,
+    /// 是否有分支因深度超限被剪去
+    depth_cutoff: bool,
+    /// 是否有含变量的命题因循环论证被剪去
+    cycle_cutoff: bool,
+    /// 是否有分支因否定中含有未确定的变量而失败
+    floundered: bool,
+    /// 终止搜索的限制
+    aborted: Option<Limit>,
+    /// 正在证明的祖先命题，由外到内排列
+    call_stack: Vec<Atom>,
+    /// 祖先命题按谓词与入栈时已确定的第一个参数分组，值为其在`call_stack`中的位置
+    ancestors: HashMap<AncestorKey, Vec<usize>>,
+    /// 已知事实与得证的引理，构造证明树时同时记录其证明
+    facts: FactStore,
+    build_tree: bool,
+    /// 已完成的子证明，构造证明树时使用
+    trees: Vec<ProofTree>,
+    /// 表格化谓词各调用模式的答案表
+    tables: Vec<Table>,
+    /// 正在求值的答案表，由外到内排列
+    generators: Vec<Generator>,
+    /// 所有答案表中累计加入的答案个数，用于判断是否达到不动点
+    table_answers: usize,
+    /// 收集到的证明过程，为None时直接输出
+    trace: Option<Vec<String>>,
+}
+
+/// 表格化谓词的一个调用模式及其已求得的答案
+struct Table {
+    goal: Atom,
+    /// 答案及其证明
+    answers: Vec<(Atom, Option<ProofTree>)>,
+    /// 答案是否已全部求得
+    complete: bool,
+}
+
+/// 正在求值的答案表
+struct Generator {
+    table: usize,
+    /// 求值过程中读取过的、位于栈中最靠外的未完成答案表的位置。
+    /// 等于自身位置时该表不依赖更外层的表，可在达到不动点后完成
+    leader: usize,
+    /// 依赖本表而尚未完成的内层答案表，随本表一同完成
+    pending: Vec<usize>,
+    /// 本轮求值得到的答案及其证明，一轮结束后并入答案表
+    found: Vec<(Atom, Option<ProofTree>)>,
+}
+
+impl<'a> Search<'a> {
+    fn new(kb: &'a KB, config: &'a SearchConfig, build_tree: bool) -> Self {
+        let index = RuleIndex::new(kb);
+        let facts = FactStore::new(kb, build_tree);
+        Search::with_store(kb, config, index, facts, build_tree)
+    }
+
+    /// 以已建立的规则索引与事实库构造搜索
+    fn with_store(
+        kb: &'a KB,
+        config: &'a SearchConfig,
+        index: RuleIndex,
+        facts: FactStore,
+        build_tree: bool,
+    ) -> Self {
+        Search {
+            kb,
+            index,
+            config,
+            max_depth: config.max_depth,
+            deadline: config
+                .timeout
+                .and_then(|timeout| Instant::now().checked_add(timeout)),
+            call_time: 0,
+            fresh: 0,
+            bindings: Bindings::new(),
+            goals: None,
+            choices: Vec::new(),
+            undo: Vec::new(),
+            depth_cutoff: false,
+            cycle_cutoff: false,
+            floundered: false,
+            aborted: None,
+            call_stack: Vec::new(),
+            ancestors: HashMap::new(),
+            facts,
+            build_tree,
+            trees: Vec::new(),
+            tables: Vec::new(),
+            generators: Vec::new(),
+            table_answers: 0,
+            trace: None,
+        }
+    }
+
+    /// 输出一行证明过程
+    fn trace(&mut self, line: Arguments) {
+        if self.config.verbose {
+            write_trace(&mut self.trace, line, false);
+        }
+    }
+
+    /// 输出一行回退的原因
+    fn trace_backtrack(&mut self, line: Arguments) {
+        if self.config.verbose {
+            write_trace(&mut self.trace, line, true);
+        }
+    }
+
+    /// ## 以给定的深度限制重新开始证明命题
+    /// 已得证的事实、步数与截止时间保留，答案表随深度限制而不同，重新建立
+    fn restart(&mut self, theorem: &Atom, max_depth: usize) {
+        if self.config.strategy == Strategy::IterativeDeepening {
+            self.trace(format_args!("深度上限：{max_depth}"));
+        }
+        self.max_depth = max_depth;
+        self.depth_cutoff = false;
+        self.cycle_cutoff = false;
+        self.floundered = false;
+        self.bindings = Bindings::new();
+        self.goals = Some(Goals::default().push(Goal::Prove(theorem.clone(), 0)));
+        self.choices.clear();
+        self.undo.clear();
+        self.call_stack.clear();
+        self.ancestors.clear();
+        self.trees.clear();
+        self.tables.clear();
+        self.generators.clear();
+    }
+
+    /// 检查推理步数与时间是否已用尽
+    fn exhausted(&self) -> Option<Limit> {
+        if self
+            .config
+            .max_steps
+            .is_some_and(|max_steps| self.call_time >= max_steps)
+        {
+            Some(Limit::Steps)
+        } else if self
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            Some(Limit::Timeout)
+        } else {
+            None
+        }
+    }
+
+    /// ## 反向链接算法的主循环
+    /// 依次证明目标链表中的命题，其中每条命题的前提包含排在其之前的所有命题
+    /// 如：为证明0<9，找到的一条可行路径需要证明存在x使得0<x且x<9
+    /// 因此目标链表为0<x,x<9
+    /// 算法在通过找到x=x_0证明0<x后不立刻认为0<x得证
+    /// 而是将x=x_0代入x<9
+    /// 如果x_0不满足x<9则认为证明失败，算法回退到最近的选择点，采取其他可行路径证明0<9
+    /// 当然如果x_0满足了x<9证明就成功了。
+    /// 目标与选择点均保存在堆上，推理深度只受深度、步数与时间限制约束，不受调用栈大小限制。
+    /// 全部命题得证时返回true，此时绑定表与证明树栈即为该证明的状态，再次调用时回退寻找下一个证明；
+    /// 搜索穷尽或触发步数、时间限制时返回false。
+    /// 与某个尚未得证的祖先命题互为变体的命题是循环论证，直接剪去。
+    /// 不含变量的命题与祖先相同时，用到它的证明总能改用祖先之下更短的证明，剪去不会遗漏解；
+    /// 含变量的命题则可能还有祖先无法给出的答案，剪去后记下`Limit::Cycle`，表明搜索不完整
+    fn bc_core(&mut self) -> bool {
+        while self.aborted.is_none() {
+            let Some(goals) = self.goals.take() else {
+                if self.backtrack() {
+                    continue;
+                }
+                return false;
+            };
+            let Some((goal, rest)) = goals.split_first() else {
+                return true;
+            };
+            let rest = rest.clone();
+            match goal {
+                Goal::Prove(theorem, depth) => self.prove(theorem, *depth, rest),
+                Goal::Refute(negated, depth) => self.negation(negated, *depth, rest),
+                Goal::Proved {
+                    theorem,
+                    rule,
+                    thetas,
+                    conditions,
+                } => self.proved(theorem, *rule, thetas, *conditions, rest),
+                Goal::Answer(theorem) => self.answer(theorem),
+            }
+        }
+        false
+    }
+
+    /// ## 回退到最近的选择点并尝试其下一个分支
+    /// 选择点的分支全部失败时将其弹出，继续回退到更早的选择点。没有可回退的选择点时返回false
+    fn backtrack(&mut self) -> bool {
+        while let Some(mut choice) = self.choices.pop() {
+            self.bindings.undo(choice.bindings);
+            self.undo_to(choice.undo);
+            let resumed = match &mut choice.branches {
+                Branches::Rules {
+                    theorem,
                     depth,
-                    max_depth,
-                    facts,
-                )
-                .is_ok()
-            {
-                if verbose {
-                    println!("{}得到了证明", subst_theorem);
+                    rules,
+                    rest,
+                } => self.next_rule(theorem, *depth, rules, rest),
+                Branches::Answers {
+                    theorem,
+                    answers,
+                    rest,
+                } => self.next_answer(theorem, answers, rest),
+                Branches::Generator { .. } => {
+                    self.fixpoint(choice);
+                    continue;
                 }
-                if !subst_theorem.contains_var() {
-                    facts.push(subst_theorem.clone());
+            };
+            if resumed {
+                if !choice.branches.is_empty() {
+                    self.choices.push(choice);
                 }
-                *thetas = tmp_thetas;
-                call_stack.pop();
-                return Ok(());
+                return true;
             }
         }
+        false
+    }
+
+    /// 逆序撤销`mark`之后对证明树栈与祖先命题栈的修改
+    fn undo_to(&mut self, mark: usize) {
+        while self.undo.len() > mark {
+            match self.undo.pop().unwrap() {
+                Undo::Tree => {
+                    self.trees.pop();
+                }
+                Undo::Merge => {
+                    let node = self.trees.pop().unwrap();
+                    self.trees.extend(node.children);
+                }
+                Undo::Call => {
+                    self.pop_ancestor();
+                }
+                Undo::Return(ancestor) => self.push_ancestor(ancestor),
+            }
+        }
+    }
+
+    fn push_ancestor(&mut self, ancestor: Atom) {
+        let key = ancestor_key(&ancestor);
+        self.ancestors
+            .entry(key)
+            .or_default()
+            .push(self.call_stack.len());
+        self.call_stack.push(ancestor);
+    }
+
+    fn pop_ancestor(&mut self) -> Option<Atom> {
+        let ancestor = self.call_stack.pop()?;
+        if let Some(positions) = self.ancestors.get_mut(&ancestor_key(&ancestor)) {
+            positions.pop();
+        }
+        Some(ancestor)
+    }
+
+    /// ## 判断命题是否与某个祖先命题互为变体
+    /// 入栈时第一个参数已确定的祖先之后仍是该值，只可能与第一个参数相同的命题互为变体；
+    /// 其余祖先的第一个参数可能在之后被绑定，总要检查。只有这些候选需要代入当前的绑定后比较
+    fn is_cycle(&self, theorem: &Atom) -> bool {
+        let open = (theorem.indicator(), None);
+        let ground = theorem
+            .args
+            .first()
+            .filter(|arg| !arg.contains_var())
+            .map(|arg| (theorem.indicator(), Some(arg.clone())));
+        [Some(open), ground]
+            .iter()
+            .flatten()
+            .filter_map(|key| self.ancestors.get(key))
+            .flatten()
+            .any(|&position| {
+                is_variant(
+                    &exhaust_subst(&self.call_stack[position], &self.bindings),
+                    theorem,
+                )
+            })
+    }
+
+    /// 建立选择点，当前分支随即失败，由回退尝试其第一个分支
+    fn choose(&mut self, branches: Branches) {
+        self.choices.push(ChoicePoint {
+            bindings: self.bindings.mark(),
+            undo: self.undo.len(),
+            branches,
+        });
+    }
+
+    /// 证明链表头部的命题，`rest`为其余目标
+    fn prove(&mut self, theorem: &Atom, depth: usize, rest: Goals) {
+        let subst_theorem = exhaust_subst(theorem, &self.bindings);
+        if let Some(fact) = self.facts.get(&subst_theorem) {
+            if let Some(proof) = fact.proof.clone() {
+                self.trees.push(proof);
+                self.undo.push(Undo::Tree);
+            }
+            self.goals = Some(rest);
+            return;
+        }
+        if self.kb.is_tabled(&subst_theorem) {
+            return self.tabled(&subst_theorem, depth, rest);
+        }
+        if self.is_cycle(&subst_theorem) {
+            self.trace_backtrack(format_args!("证明{subst_theorem}是循环论证，回退"));
+            if subst_theorem.contains_var() {
+                self.cycle_cutoff = true;
+            }
+            return;
+        }
+        if self.within_limits(&subst_theorem, depth) {
+            self.resolve(&subst_theorem, depth, rest);
+        }
+    }
+
+    /// ## 以有限失败证明否定
+    /// 被否定的命题须不含变量，否则其成立与否取决于变量之后的绑定（floundering），
+    /// 此时当前分支失败并记下，其余分支照常搜索。
+    /// 另起一次搜索证明被否定的命题，借用当前搜索的规则索引与事实库，共用步数与截止时间，
+    /// 深度限制为当前剩余的深度：只有搜索完整且仍无法证明时否定才得证；
+    /// 找到证明时当前分支失败；搜索因深度超限、循环论证或floundering剪去了分支时无法断定否定成立，
+    /// 当前分支同样失败，并将相应的剪枝记入当前搜索
+    fn negation(&mut self, negated: &Atom, depth: usize, rest: Goals) {
+        let negated = exhaust_subst(negated, &self.bindings);
+        let theorem = Literal::Neg(negated.clone());
+        if negated.contains_var() {
+            self.trace_backtrack(format_args!("{theorem}中含有未确定的变量，回退"));
+            self.floundered = true;
+            return;
+        }
+        if !self.within_limits(&theorem, depth) {
+            return;
+        }
+        self.trace(format_args!("对{theorem}的证明："));
+        let index = std::mem::take(&mut self.index);
+        let facts = std::mem::take(&mut self.facts);
+        let mut search = Search::with_store(self.kb, self.config, index, facts, self.build_tree);
+        search.max_depth = self.max_depth - depth;
+        search.deadline = self.deadline;
+        search.call_time = self.call_time;
+        search.goals = Some(Goals::default().push(Goal::Prove(negated.clone(), 0)));
+        search.trace = self.trace.take();
+        let proved = search.bc_core();
+        self.index = search.index;
+        self.facts = search.facts;
+        self.trace = search.trace;
+        self.call_time = search.call_time;
+        if let Some(limit) = search.aborted {
+            self.aborted = Some(limit);
+        } else if proved {
+            self.trace_backtrack(format_args!("{negated}得证，{theorem}不成立，回退"));
+        } else if search.depth_cutoff || search.cycle_cutoff || search.floundered {
+            self.trace_backtrack(format_args!(
+                "搜索剪去了部分分支，无法确定{theorem}是否成立，回退"
+            ));
+            self.depth_cutoff |= search.depth_cutoff;
+            self.cycle_cutoff |= search.cycle_cutoff;
+            self.floundered |= search.floundered;
+        } else {
+            if self.build_tree {
+                self.trees.push(ProofTree::negation(negated));
+                self.undo.push(Undo::Tree);
+            }
+            self.goals = Some(rest);
+        }
+    }
+
+    /// 检查深度、步数与时间限制，未触发限制时返回true
+    fn within_limits(&mut self, theorem: &impl Display, depth: usize) -> bool {
+        if depth > self.max_depth {
+            self.trace_backtrack(format_args!("尝试证明{theorem}时深度超限，回退"));
+            self.depth_cutoff = true;
+            return false;
+        }
+        if let Some(limit) = self.exhausted() {
+            self.trace_backtrack(format_args!("尝试证明{theorem}时{limit}，终止搜索"));
+            self.aborted = Some(limit);
+            return false;
+        }
+        true
+    }
+
+    /// 用知识库中的规则展开命题，为每条结论可能与之合一的规则建立一个分支。
+    /// 常量事实从事实库中查找，其余规则只有索引给出的候选才会被标准化
+    fn resolve(&mut self, theorem: &Atom, depth: usize, rest: Goals) {
+        self.trace(format_args!("对{theorem}的证明："));
+        self.call_time += 1;
+        self.fresh += 1;
+        let mut rules: Vec<(usize, Rule)> = self
+            .index
+            .candidates(theorem)
+            .into_iter()
+            .map(|i| (i, KB::rule_standardize(&self.kb.rules[i], self.fresh)))
+            .collect();
+        rules.extend(self.facts.matching(theorem).filter_map(|fact| {
+            let rule = Rule {
+                condition: Vec::new(),
+                conclusion: fact.atom.clone(),
+            };
+            Some((fact.rule?, rule))
+        }));
+        rules.sort_by_key(|&(i, _)| i);
+        self.choose(Branches::Rules {
+            theorem: theorem.clone(),
+            depth,
+            rules: rules.into_iter(),
+            rest,
+        });
+    }
+
+    /// 依次尝试候选规则，找到结论能与命题合一的规则时将其前提加入目标链表
+    fn next_rule(
+        &mut self,
+        theorem: &Atom,
+        depth: usize,
+        rules: &mut std::vec::IntoIter<(usize, Rule)>,
+        rest: &Goals,
+    ) -> bool {
+        for (i, rule) in rules.by_ref() {
+            let mark = self.bindings.mark();
+            if unify(
+                theorem,
+                &rule.conclusion,
+                &mut self.bindings,
+                self.config.occurs_check,
+            )
+            .is_err()
+            {
+                continue;
+            }
+            self.push_ancestor(theorem.clone());
+            self.undo.push(Undo::Call);
+            let conditions = rule.condition.len();
+            let mut goals = rest.push(Goal::Proved {
+                theorem: theorem.clone(),
+                rule: i,
+                thetas: if self.build_tree {
+                    self.bindings.since(mark)
+                } else {
+                    Vec::new()
+                },
+                conditions,
+            });
+            for condition in rule.condition.into_iter().rev() {
+                goals = goals.push(match condition {
+                    Literal::Pos(atom) => Goal::Prove(atom, depth + 1),
+                    Literal::Neg(atom) => Goal::Refute(atom, depth + 1),
+                });
+            }
+            self.goals = Some(goals);
+            return true;
+        }
+        false
+    }
+
+    /// ## 表格化求解
+    /// 命题的每个调用模式（变体视为相同）对应一张答案表。
+    /// 首次调用时反复用规则求出新答案，直至一轮求值中所有答案表都不再增加；
+    /// 求值中递归调用尚未完成的答案表时只读取其已有答案，
+    /// 该表所在的一组相互依赖的表在最外层的表达到不动点后一并完成。
+    /// 求值结束后逐个取出答案与命题合一，继续证明剩余命题
+    fn tabled(&mut self, theorem: &Atom, depth: usize, rest: Goals) {
+        let found = self
+            .tables
+            .iter()
+            .position(|table| is_variant(&table.goal, theorem));
+        if let Some(table) = found {
+            if self.tables[table].complete {
+                return self.consume(table, theorem, rest);
+            }
+            if let Some(position) = self.generators.iter().position(|g| g.table == table) {
+                let top = self.generators.last_mut().unwrap();
+                top.leader = top.leader.min(position);
+                return self.consume(table, theorem, rest);
+            }
+        }
+        if !self.within_limits(theorem, depth) {
+            return;
+        }
+        let table = found.unwrap_or_else(|| {
+            self.tables.push(Table {
+                goal: theorem.clone(),
+                answers: Vec::new(),
+                complete: false,
+            });
+            self.tables.len() - 1
+        });
+        let position = self.generators.len();
+        self.generators.push(Generator {
+            table,
+            leader: position,
+            pending: Vec::new(),
+            found: Vec::new(),
+        });
+        self.choose(Branches::Generator {
+            table,
+            theorem: theorem.clone(),
+            depth,
+            rest,
+            before: self.table_answers,
+        });
+        self.evaluate(theorem, depth);
+    }
+
+    /// 对正在求值的答案表进行一轮求值，每得到一个答案都记入栈顶的求值状态
+    fn evaluate(&mut self, theorem: &Atom, depth: usize) {
+        let goals = Goals::default().push(Goal::Answer(theorem.clone()));
+        self.resolve(theorem, depth, goals);
+    }
+
+    /// 记录表格化求值得到的答案，随后回退寻找其他答案
+    fn answer(&mut self, theorem: &Atom) {
+        let answer = exhaust_subst(theorem, &self.bindings);
+        let proof = self.trees.last();
+        let proof = proof.map(|proof| proof.resolve(&self.bindings));
+        // 内层的求值在其答案流向外层之前均已结束，得到答案的必为栈顶的答案表
+        let generator = self.generators.last_mut().unwrap();
+        generator.found.push((answer, proof));
+    }
+
+    /// ## 表格化求值的一轮结束
+    /// 将本轮得到的新答案并入答案表，答案有所增加时再求值一轮；
+    /// 否则完成该表，或将其交由外层的表一同完成，并逐个取出答案继续证明剩余命题
+    fn fixpoint(&mut self, choice: ChoicePoint) {
+        let Branches::Generator {
+            table,
+            theorem,
+            depth,
+            rest,
+            before,
+        } = choice.branches
+        else {
+            unreachable!()
+        };
+        let found = std::mem::take(&mut self.generators.last_mut().unwrap().found);
+        for (answer, proof) in found {
+            let answers = &mut self.tables[table].answers;
+            if !answers.iter().any(|(a, _)| is_variant(a, &answer)) {
+                answers.push((answer, proof));
+                self.table_answers += 1;
+            }
+        }
+        if self.table_answers != before {
+            self.choices.push(ChoicePoint {
+                branches: Branches::Generator {
+                    table,
+                    theorem: theorem.clone(),
+                    depth,
+                    rest,
+                    before: self.table_answers,
+                },
+                ..choice
+            });
+            return self.evaluate(&theorem, depth);
+        }
+        let generator = self.generators.pop().unwrap();
+        if generator.leader == self.generators.len() {
+            self.tables[table].complete = true;
+            for pending in generator.pending {
+                self.tables[pending].complete = true;
+            }
+            let answers = self.tables[table].answers.len();
+            self.trace(format_args!("{theorem}的答案表已完成，共{answers}个答案"));
+        } else {
+            let parent = self.generators.last_mut().unwrap();
+            parent.leader = parent.leader.min(generator.leader);
+            parent.pending.push(table);
+            parent.pending.extend(generator.pending);
+        }
+        self.consume(table, &theorem, rest);
+    }
+
+    /// 为答案表中已有的答案建立选择点，逐个取出与命题合一后继续证明剩余命题
+    fn consume(&mut self, table: usize, theorem: &Atom, rest: Goals) {
+        let answers = self.tables[table].answers.clone();
+        self.choose(Branches::Answers {
+            theorem: theorem.clone(),
+            answers: answers.into_iter(),
+            rest,
+        });
+    }
+
+    /// 依次尝试答案表中的答案，找到能与命题合一的答案时继续证明剩余命题
+    fn next_answer(
+        &mut self,
+        theorem: &Atom,
+        answers: &mut std::vec::IntoIter<(Atom, Option<ProofTree>)>,
+        rest: &Goals,
+    ) -> bool {
+        for (answer, proof) in answers.by_ref() {
+            let answer = answer.rename_apart(&mut self.fresh);
+            if unify(
+                theorem,
+                &answer,
+                &mut self.bindings,
+                self.config.occurs_check,
+            )
+            .is_err()
+            {
+                continue;
+            }
+            if let Some(proof) = proof {
+                self.trees.push(proof);
+                self.undo.push(Undo::Tree);
+            }
+            self.goals = Some(rest.clone());
+            return true;
+        }
+        false
+    }
+
+    /// ## 处理前提已全部得证的命题
+    /// 将命题记为已知事实，构造证明树时把前提的子证明合并为该命题的证明，
+    /// 随后继续证明剩余命题。对证明树与祖先命题栈的修改均记入撤销记录
+    fn proved(
+        &mut self,
+        theorem: &Atom,
+        rule: usize,
+        applied: &[Theta],
+        conditions: usize,
+        rest: Goals,
+    ) {
+        let proved = exhaust_subst(theorem, &self.bindings);
+        self.trace(format_args!("{proved}得到了证明"));
+        if self.build_tree {
+            let children = self.trees.split_off(self.trees.len() - conditions);
+            self.trees.push(ProofTree {
+                goal: theorem.clone(),
+                negated: false,
+                rule: Some(rule),
+                thetas: applied.to_vec(),
+                children,
+            });
+            self.undo.push(Undo::Merge);
+        }
+        if !proved.contains_var() && self.facts.get(&proved).is_none() {
+            let proof = self.trees.last().filter(|_| self.build_tree);
+            let proof = proof.map(|proof| proof.resolve(&self.bindings));
+            self.facts.learn(proved, proof);
+        }
+        // 该命题已得证，在证明剩余命题时不再是祖先命题
+        if let Some(ancestor) = self.pop_ancestor() {
+            self.undo.push(Undo::Return(ancestor));
+        }
+        self.goals = Some(rest);
     }
-    Err(ReasoningError::ProofNotFound)
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
     use crate::{Rule, pred, val, var};
-    #[test]
-    fn test_bc_example1() {
-        let kb = KB {
+    fn crime_kb() -> KB {
+        KB {
             rules: vec![
                 Rule {
                     condition: vec![
-                        pred("american", vec![var("x")]),
-                        pred("weapon", vec![var("y")]),
-                        pred("sells", vec![var("x"), var("y"), var("z")]),
-                        pred("hostile", vec![var("z")]),
+                        pred("american", vec![var("x")]).into(),
+                        pred("weapon", vec![var("y")]).into(),
+                        pred("sells", vec![var("x"), var("y"), var("z")]).into(),
+                        pred("hostile", vec![var("z")]).into(),
                     ],
                     conclusion: pred("criminal", vec![var("x")]),
                 },
                 Rule {
                     condition: vec![
-                        pred("missile", vec![var("x")]),
-                        pred("owns", vec![val("nono"), var("x")]),
+                        pred("missile", vec![var("x")]).into(),
+                        pred("owns", vec![val("nono"), var("x")]).into(),
                     ],
                     conclusion: pred("sells", vec![val("west"), var("x"), val("nono")]),
                 },
                 Rule {
-                    condition: vec![pred("missile", vec![var("x")])],
+                    condition: vec![pred("missile", vec![var("x")]).into()],
                     conclusion: pred("weapon", vec![var("x")]),
                 },
                 Rule {
-                    condition: vec![pred("enemy", vec![var("x"), val("america")])],
+                    condition: vec![pred("enemy", vec![var("x"), val("america")]).into()],
                     conclusion: pred("hostile", vec![var("x")]),
                 },
                 Rule {
@@ -202,11 +1291,479 @@ mod test {
                     conclusion: pred("enemy", vec![val("nono"), val("america")]),
                 },
             ],
-        };
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_bc_example1() {
+        let kb = crime_kb();
         let theorem_true = pred("criminal", vec![val("west")]);
         let json = serde_json::to_string_pretty(&kb).unwrap();
         std::fs::write("knowledge_base.json", json).unwrap();
         println!("start");
-        bc(&kb, &theorem_true, true, 5).unwrap();
+        bc(
+            &kb,
+            &theorem_true,
+            &SearchConfig {
+                verbose: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let theorem_var = pred("sells", vec![val("west"), var("y"), var("z")]);
+        let answer = bc(&kb, &theorem_var, &SearchConfig::default()).unwrap();
+        let answer: Vec<String> = answer.iter().map(|theta| theta.to_string()).collect();
+        assert_eq!(answer, vec!["Y = m1", "Z = nono"]);
+    }
+
+    #[test]
+    fn test_bc_answer_free_var() {
+        let kb = KB {
+            rules: vec![Rule {
+                condition: vec![],
+                conclusion: pred("same", vec![var("x"), var("x")]),
+            }],
+            ..Default::default()
+        };
+        let theorem = pred("same", vec![var("a"), var("b")]);
+        let answer = bc(&kb, &theorem, &SearchConfig::default()).unwrap();
+        let answer: Vec<String> = answer.iter().map(|theta| theta.to_string()).collect();
+        assert_eq!(answer, vec!["B = A"]);
+    }
+
+    #[test]
+    fn test_bc_standardize_apart() {
+        // 规则中的X在第1步改名后不能与待证命题中的X1重名
+        let kb = crate::parser::parse_kb("p(X, b).").unwrap();
+        let theorem = pred("p", vec![val("a"), var("X1")]);
+        let answer = bc(&kb, &theorem, &SearchConfig::default()).unwrap();
+        assert_eq!(answer[0].to_string(), "X1 = b");
+    }
+
+    #[test]
+    fn test_bc_anonymous() {
+        // 匿名变量不给出绑定，仅匿名变量的取值不同的解视为同一个解；书写的`_G1`是普通变量
+        let kb = crate::parser::parse_kb("p(a, b). p(a, c). p(b, b).").unwrap();
+        let theorem = crate::parser::parse_atom("p(_G1, _)").unwrap();
+        for strategy in [Strategy::DepthFirst, Strategy::Datalog] {
+            let config = SearchConfig {
+                strategy,
+                ..Default::default()
+            };
+            let mut answers = Vec::<String>::new();
+            bc_solutions(&kb, &theorem, &config, |solution| {
+                let bindings: Vec<String> = solution.iter().map(Theta::to_string).collect();
+                answers.push(bindings.join(", "));
+                ControlFlow::Continue(())
+            });
+            assert_eq!(answers, vec!["_G1 = a", "_G1 = b"]);
+        }
+    }
+
+    #[test]
+    fn test_bc_solutions() {
+        let kb = KB {
+            rules: vec![
+                Rule {
+                    condition: vec![],
+                    conclusion: pred("parent", vec![val("tom"), val("bob")]),
+                },
+                Rule {
+                    condition: vec![],
+                    conclusion: pred("parent", vec![val("bob"), val("ann")]),
+                },
+                Rule {
+                    condition: vec![],
+                    conclusion: pred("parent", vec![val("bob"), val("pat")]),
+                },
+                Rule {
+                    condition: vec![pred("parent", vec![var("x"), var("y")]).into()],
+                    conclusion: pred("ancestor", vec![var("x"), var("y")]),
+                },
+                Rule {
+                    condition: vec![
+                        pred("parent", vec![var("x"), var("y")]).into(),
+                        pred("ancestor", vec![var("y"), var("z")]).into(),
+                    ],
+                    conclusion: pred("ancestor", vec![var("x"), var("z")]),
+                },
+            ],
+            ..Default::default()
+        };
+        let theorem = pred("ancestor", vec![val("tom"), var("who")]);
+        let mut answers = Vec::<String>::new();
+        let summary = bc_solutions(&kb, &theorem, &SearchConfig::default(), |solution| {
+            answers.push(solution[0].to_string());
+            ControlFlow::Continue(())
+        });
+        assert_eq!(summary.solutions, 3);
+        assert_eq!(answers, vec!["WHO = bob", "WHO = ann", "WHO = pat"]);
+        let summary = bc_solutions(&kb, &theorem, &SearchConfig::default(), |_| {
+            ControlFlow::Break(())
+        });
+        assert_eq!(summary.solutions, 1);
+    }
+
+    #[test]
+    fn test_bc_proof_tree() {
+        let kb = crime_kb();
+        let theorem = pred("criminal", vec![val("west")]);
+        let (answer, proof) = bc_proof(&kb, &theorem, &SearchConfig::default()).unwrap();
+        assert!(answer.is_empty());
+        assert_eq!(proof.goal, theorem);
+        assert_eq!(proof.rule, Some(0));
+        let goals: Vec<String> = proof.children.iter().map(|c| c.goal.to_string()).collect();
+        assert_eq!(
+            goals,
+            vec![
+                "american(west)",
+                "weapon(m1)",
+                "sells(west, m1, nono)",
+                "hostile(nono)"
+            ]
+        );
+        let rules: Vec<Option<usize>> = proof.children.iter().map(|c| c.rule).collect();
+        assert_eq!(rules, vec![Some(6), Some(2), Some(1), Some(3)]);
+        assert_eq!(
+            proof.children[1].children[0].goal,
+            pred("missile", vec![val("m1")])
+        );
+        let json = serde_json::to_string(&proof).unwrap();
+        let parsed: ProofTree = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.children[2].children.len(), 2);
+    }
+
+    #[test]
+    fn test_bc_limits() {
+        let kb = crime_kb();
+        let theorem = pred("criminal", vec![val("west")]);
+        let shallow = SearchConfig {
+            max_depth: 1,
+            ..Default::default()
+        };
+        assert!(matches!(
+            bc(&kb, &theorem, &shallow),
+            Err(ReasoningError::DepthLimitExceed)
+        ));
+        let few_steps = SearchConfig {
+            max_steps: Some(2),
+            ..Default::default()
+        };
+        assert!(matches!(
+            bc(&kb, &theorem, &few_steps),
+            Err(ReasoningError::StepLimitExceed)
+        ));
+        let no_time = SearchConfig {
+            timeout: Some(Duration::ZERO),
+            ..Default::default()
+        };
+        assert!(matches!(
+            bc(&kb, &theorem, &no_time),
+            Err(ReasoningError::Timeout)
+        ));
+        // 截止时间无法表示时不限时
+        let forever = SearchConfig {
+            timeout: Some(Duration::MAX),
+            ..Default::default()
+        };
+        assert!(bc(&kb, &theorem, &forever).is_ok());
+        let theorem_false = pred("criminal", vec![val("nono")]);
+        assert!(matches!(
+            bc(&kb, &theorem_false, &SearchConfig::default()),
+            Err(ReasoningError::ProofNotFound)
+        ));
+    }
+
+    #[test]
+    fn test_bc_outcome() {
+        let kb = crime_kb();
+        let theorem = pred("criminal", vec![val("west")]);
+        let summary = bc_solutions(&kb, &theorem, &SearchConfig::default(), |_| {
+            ControlFlow::Break(())
+        });
+        assert_eq!(summary.outcome, Outcome::Proved);
+        let shallow = SearchConfig {
+            max_depth: 1,
+            ..Default::default()
+        };
+        let summary = bc_solutions(&kb, &theorem, &shallow, |_| ControlFlow::Continue(()));
+        assert_eq!(summary.outcome, Outcome::Unknown(Limit::Depth));
+        let theorem_false = pred("criminal", vec![val("nono")]);
+        let summary = bc_solutions(&kb, &theorem_false, &SearchConfig::default(), |_| {
+            ControlFlow::Continue(())
+        });
+        assert_eq!(summary.outcome, Outcome::Disproved);
+    }
+
+    #[test]
+    fn test_bc_iterative_deepening() {
+        let kb = crate::parser::parse_kb(
+            "p(X) :- q(X). q(X) :- r(X). r(deep). p(shallow). \
+             loop(X) :- loop(f(X)).",
+        )
+        .unwrap();
+        let theorem = pred("p", vec![var("x")]);
+        let deepening = SearchConfig {
+            strategy: Strategy::IterativeDeepening,
+            ..Default::default()
+        };
+        let answer = bc(&kb, &theorem, &SearchConfig::default()).unwrap();
+        assert_eq!(answer[0].to_string(), "X = deep");
+        let answer = bc(&kb, &theorem, &deepening).unwrap();
+        assert_eq!(answer[0].to_string(), "X = shallow");
+        let mut answers = Vec::<String>::new();
+        bc_solutions(&kb, &theorem, &deepening, |solution| {
+            answers.push(solution[0].to_string());
+            ControlFlow::Continue(())
+        });
+        assert_eq!(answers, vec!["X = shallow", "X = deep"]);
+        let budget = SearchConfig {
+            max_depth: usize::MAX,
+            max_steps: Some(100),
+            strategy: Strategy::IterativeDeepening,
+            ..Default::default()
+        };
+        let summary = bc_solutions(&kb, &pred("loop", vec![val("a")]), &budget, |_| {
+            ControlFlow::Continue(())
+        });
+        assert_eq!(summary.outcome, Outcome::Unknown(Limit::Steps));
+        let summary = bc_solutions(&kb, &pred("r", vec![val("shallow")]), &budget, |_| {
+            ControlFlow::Continue(())
+        });
+        assert_eq!(summary.outcome, Outcome::Disproved);
+    }
+
+    #[test]
+    fn test_bc_loop_check() {
+        let kb = crate::parser::parse_kb(
+            "path(X, Z) :- path(X, Y), edge(Y, Z). path(X, Y) :- edge(X, Y). \
+             edge(a, b). edge(b, c).",
+        )
+        .unwrap();
+        let deep = SearchConfig {
+            max_depth: 50,
+            ..Default::default()
+        };
+        let summary = bc_solutions(&kb, &pred("path", vec![val("a"), val("c")]), &deep, |_| {
+            ControlFlow::Break(())
+        });
+        assert_eq!(summary.outcome, Outcome::Proved);
+        // 剪去了含变量的path(c, Y)，无法断定命题不成立
+        let summary = bc_solutions(&kb, &pred("path", vec![val("c"), val("a")]), &deep, |_| {
+            ControlFlow::Continue(())
+        });
+        assert_eq!(summary.outcome, Outcome::Unknown(Limit::Cycle));
+    }
+
+    #[test]
+    fn test_bc_long_chain() {
+        let n = 300;
+        let mut source: String = (0..n)
+            .map(|i| format!("edge(n{i}, n{}). ", i + 1))
+            .collect();
+        source.push_str("reach(X, Y) :- edge(X, Y). reach(X, Y) :- edge(X, Z), reach(Z, Y).");
+        let kb = crate::parser::parse_kb(&source).unwrap();
+        let config = SearchConfig {
+            max_depth: 2 * n,
+            ..Default::default()
+        };
+        let mut answers = 0;
+        let summary = bc_solutions(
+            &kb,
+            &pred("reach", vec![val("n0"), var("y")]),
+            &config,
+            |_| {
+                answers += 1;
+                ControlFlow::Continue(())
+            },
+        );
+        assert_eq!(answers, n);
+        assert_eq!(summary.limit, None);
+    }
+
+    #[test]
+    fn test_bc_loop_check_incomplete() {
+        let source = "s(X) :- q(X), isc(X). isc(c). q(b). q(X) :- q(Y), r(X, Y). r(c, b).";
+        let kb = crate::parser::parse_kb(source).unwrap();
+        let config = SearchConfig::default();
+        // q(c)需要展开与祖先q(X)互为变体的q(Y)，剪去后找不到解，但不能报告命题不成立
+        let summary = bc_solutions(&kb, &pred("s", vec![var("z")]), &config, |_| {
+            ControlFlow::Continue(())
+        });
+        assert_eq!(summary.outcome, Outcome::Unknown(Limit::Cycle));
+        assert!(bc(&kb, &pred("s", vec![val("c")]), &config).is_ok());
+        // 不含变量的命题与祖先相同时剪去不会遗漏解
+        let summary = bc_solutions(&kb, &pred("s", vec![val("b")]), &config, |_| {
+            ControlFlow::Continue(())
+        });
+        assert_eq!(summary.outcome, Outcome::Disproved);
+        // 表格化求解是完整的
+        let kb = crate::parser::parse_kb(&format!(":- table q/1.\n{source}")).unwrap();
+        let mut answers = Vec::<String>::new();
+        let summary = bc_solutions(&kb, &pred("s", vec![var("z")]), &config, |s| {
+            answers.push(s[0].to_string());
+            ControlFlow::Continue(())
+        });
+        assert_eq!(answers, vec!["Z = c"]);
+        assert_eq!(summary.limit, None);
+    }
+
+    #[test]
+    fn test_bc_tabling() {
+        let kb = crate::parser::parse_kb(
+            ":- table path/2.\n\
+             path(X, Z) :- path(X, Y), edge(Y, Z). path(X, Y) :- edge(X, Y).\n\
+             edge(a, b). edge(b, c). edge(c, a). edge(d, a).",
+        )
+        .unwrap();
+        let deep = SearchConfig {
+            max_depth: 50,
+            ..Default::default()
+        };
+        let mut answers = Vec::<String>::new();
+        let summary = bc_solutions(&kb, &pred("path", vec![val("a"), var("x")]), &deep, |s| {
+            answers.push(s[0].to_string());
+            ControlFlow::Continue(())
+        });
+        answers.sort();
+        assert_eq!(answers, vec!["X = a", "X = b", "X = c"]);
+        assert_eq!(summary.outcome, Outcome::Proved);
+        assert_eq!(summary.limit, None);
+        let summary = bc_solutions(&kb, &pred("path", vec![val("a"), val("d")]), &deep, |_| {
+            ControlFlow::Continue(())
+        });
+        assert_eq!(summary.outcome, Outcome::Disproved);
+        let (_, proof) = bc_proof(&kb, &pred("path", vec![val("a"), val("c")]), &deep).unwrap();
+        assert_eq!(proof.goal, pred("path", vec![val("a"), val("c")]));
+        assert_eq!(
+            proof.children[0].goal,
+            pred("path", vec![val("a"), val("b")])
+        );
+        assert_eq!(
+            proof.children[1].goal,
+            pred("edge", vec![val("b"), val("c")])
+        );
+    }
+
+    #[test]
+    fn test_bc_negation() {
+        let kb = crate::parser::parse_kb(
+            "hostile(X) :- enemy(X, america), \\+ allied(X, america).
+             enemy(nono, america). enemy(wakanda, america). allied(wakanda, america).
+             bad(X) :- \\+ good(X). good(a).
+             p :- \\+ q. q :- r. r :- s. s.",
+        )
+        .unwrap();
+        let config = SearchConfig::default();
+        let mut answers = Vec::<String>::new();
+        let summary = bc_solutions(&kb, &pred("hostile", vec![var("x")]), &config, |s| {
+            answers.push(s[0].to_string());
+            ControlFlow::Continue(())
+        });
+        assert_eq!(answers, vec!["X = nono"]);
+        assert_eq!(summary.outcome, Outcome::Proved);
+        let (_, proof) = bc_proof(&kb, &pred("bad", vec![val("b")]), &config).unwrap();
+        assert_eq!(proof.children[0].literal().to_string(), "\\+ good(b)");
+        assert!(proof.children[0].to_json().contains(r#""negated": true"#));
+        assert_eq!(proof.children[0].rule, None);
+        assert!(matches!(
+            bc(&kb, &pred("bad", vec![val("a")]), &config),
+            Err(ReasoningError::ProofNotFound)
+        ));
+        // 否定中的变量尚未确定，无法以有限失败求值
+        let summary = bc_solutions(&kb, &pred("bad", vec![var("x")]), &config, |_| {
+            ControlFlow::Continue(())
+        });
+        assert_eq!(summary.outcome, Outcome::Floundered);
+        assert!(matches!(
+            bc(&kb, &pred("bad", vec![var("x")]), &config),
+            Err(ReasoningError::Floundering)
+        ));
+        // 只有含未确定变量的分支失败，其余的解照常给出
+        let partial = crate::parser::parse_kb("p(X) :- \\+ q(X). p(a).").unwrap();
+        let mut answers = Vec::<String>::new();
+        let summary = bc_solutions(&partial, &pred("p", vec![var("z")]), &config, |s| {
+            answers.push(s[0].to_string());
+            ControlFlow::Continue(())
+        });
+        assert_eq!(answers, vec!["Z = a"]);
+        assert!(summary.floundered);
+        assert_eq!(summary.outcome, Outcome::Proved);
+        // 被否定的命题因深度超限未能得证时，不能断定否定成立
+        let p = pred("p", vec![]);
+        let shallow = SearchConfig {
+            max_depth: 1,
+            ..Default::default()
+        };
+        let summary = bc_solutions(&kb, &p, &shallow, |_| ControlFlow::Continue(()));
+        assert_eq!(summary.outcome, Outcome::Unknown(Limit::Depth));
+        let summary = bc_solutions(&kb, &p, &config, |_| ControlFlow::Continue(()));
+        assert_eq!(summary.outcome, Outcome::Disproved);
+    }
+
+    #[test]
+    fn test_bc_negation_incomplete() {
+        let kb = crate::parser::parse_kb(
+            "s(X) :- q(X), isc(X). isc(c). q(b). q(X) :- q(Y), r(X, Y). r(c, b).
+             u :- \\+ w. w :- s(Z).",
+        )
+        .unwrap();
+        let config = SearchConfig::default();
+        // w的搜索因循环论证不完整，不能断定\+ w成立
+        let summary = bc_solutions(&kb, &pred("u", vec![]), &config, |_| {
+            ControlFlow::Continue(())
+        });
+        assert_eq!(summary.solutions, 0);
+        assert_eq!(summary.outcome, Outcome::Unknown(Limit::Cycle));
+    }
+
+    #[test]
+    fn test_bc_occurs_check() {
+        let kb = crate::parser::parse_kb("p(X, f(X)).").unwrap();
+        let theorem = pred("p", vec![var("y"), var("y")]);
+        assert!(matches!(
+            bc(&kb, &theorem, &SearchConfig::default()),
+            Err(ReasoningError::ProofNotFound)
+        ));
+    }
+
+    #[test]
+    fn test_bc_resume() {
+        let kb = crate::parser::parse_kb("p(a). p(b). p(c).").unwrap();
+        let config = SearchConfig::default();
+        let mut solutions = Solutions::new(&kb, &pred("p", vec![var("x")]), &config, false);
+        let first = solutions.next().unwrap();
+        assert_eq!(first.bindings[0].to_string(), "X = a");
+        assert_eq!(solutions.summary().solutions, 1);
+        // 暂停后继续，从上一个解回退寻找下一个解
+        let rest: Vec<String> = solutions.map(|s| s.bindings[0].to_string()).collect();
+        assert_eq!(rest, vec!["X = b", "X = c"]);
+    }
+
+    #[test]
+    fn test_bc_deep_derivation() {
+        // 证明需要数千层推理，只受深度限制约束，不会耗尽调用栈
+        let n = 3000;
+        let mut source = String::from("reach(X) :- edge(X, Y), reach(Y).\n");
+        for i in 0..n {
+            source += &format!("edge(n{i}, n{}).\n", i + 1);
+        }
+        source += &format!("reach(n{n}).");
+        let kb = crate::parser::parse_kb(&source).unwrap();
+        let theorem = pred("reach", vec![val("n0")]);
+        let config = SearchConfig {
+            max_depth: n,
+            ..Default::default()
+        };
+        assert!(bc(&kb, &theorem, &config).is_ok());
+        let shallow = SearchConfig {
+            max_depth: n - 1,
+            ..Default::default()
+        };
+        assert!(matches!(
+            bc(&kb, &theorem, &shallow),
+            Err(ReasoningError::DepthLimitExceed)
+        ));
     }
 }
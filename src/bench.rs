@@ -1,4 +1,4 @@
-use crate::bc::bc;
+use crate::bc::{SearchConfig, bc};
 use crate::{KB, Rule, func, pred, val, var};
 
 pub fn bench_bc_math() {
@@ -28,15 +28,15 @@ pub fn bench_bc_math() {
             },
             Rule {
                 condition: vec![
-                    pred("leq", vec![var("x"), var("y")]),
-                    pred("leq", vec![var("y"), var("z")]),
+                    pred("leq", vec![var("x"), var("y")]).into(),
+                    pred("leq", vec![var("y"), var("z")]).into(),
                 ],
                 conclusion: pred("leq", vec![var("x"), var("z")]),
             },
             Rule {
                 condition: vec![
-                    pred("leq", vec![var("w"), var("y")]),
-                    pred("leq", vec![var("x"), var("z")]),
+                    pred("leq", vec![var("w"), var("y")]).into(),
+                    pred("leq", vec![var("x"), var("z")]).into(),
                 ],
                 conclusion: pred(
                     "leq",
@@ -61,10 +61,11 @@ pub fn bench_bc_math() {
                 ),
             },
         ],
+        ..Default::default()
     };
     let theorem_true = pred(
         "leq",
         vec![val("seven"), func("add", vec![val("three"), val("nine")])],
     );
-    bc(&kb, &theorem_true, false, 5).unwrap();
+    bc(&kb, &theorem_true, &SearchConfig::default()).unwrap();
 }
@@ -0,0 +1,78 @@
+//! ## 变量绑定
+//! 搜索中所有变量的绑定保存在同一张绑定表中，按变量哈希查找。
+//! 每次绑定都记入轨迹，回退时按轨迹撤销到选择点处记下的位置，
+//! 因此尝试不同的规则时无需复制整个置换列表
+
+use super::{Symbol, Theta, Variable};
+use std::collections::HashMap;
+
+/// ## 带轨迹的绑定表
+#[derive(Debug, Default)]
+pub(crate) struct Bindings {
+    values: HashMap<Variable, Symbol>,
+    /// 按绑定顺序排列的已绑定变量
+    trail: Vec<Variable>,
+}
+
+impl Bindings {
+    pub(crate) fn new() -> Self {
+        Bindings::default()
+    }
+
+    /// 变量当前绑定的项，未绑定时返回None
+    pub(crate) fn get(&self, var: &Variable) -> Option<&Symbol> {
+        self.values.get(var)
+    }
+
+    /// 绑定一个尚未绑定的变量并记入轨迹
+    pub(crate) fn bind(&mut self, var: Variable, x: Symbol) {
+        debug_assert!(!self.values.contains_key(&var), "变量{var:?}已被绑定");
+        self.values.insert(var, x);
+        self.trail.push(var);
+    }
+
+    /// 记下当前轨迹的位置，作为之后回退的目标
+    pub(crate) fn mark(&self) -> usize {
+        self.trail.len()
+    }
+
+    /// 撤销`mark`之后的所有绑定
+    pub(crate) fn undo(&mut self, mark: usize) {
+        for var in self.trail.drain(mark..) {
+            self.values.remove(&var);
+        }
+    }
+
+    /// 按绑定顺序列出`mark`之后的绑定
+    pub(crate) fn since(&self, mark: usize) -> Vec<Theta> {
+        self.trail[mark..]
+            .iter()
+            .map(|var| Theta::new(Symbol::Var(*var), self.values[var].clone()).unwrap())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{val, var};
+
+    #[test]
+    fn test_trail_undo() {
+        let Symbol::Var(x) = var("x") else {
+            unreachable!()
+        };
+        let Symbol::Var(y) = var("y") else {
+            unreachable!()
+        };
+        let mut bindings = Bindings::new();
+        bindings.bind(x, val("a"));
+        let mark = bindings.mark();
+        bindings.bind(y, val("b"));
+        assert_eq!(bindings.since(mark)[0].to_string(), "Y = b");
+        bindings.undo(mark);
+        assert_eq!(bindings.get(&x), Some(&val("a")));
+        assert_eq!(bindings.get(&y), None);
+        assert!(bindings.since(mark).is_empty());
+    }
+}
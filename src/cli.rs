@@ -1,43 +1,322 @@
-//! ## 用于反向链接推理的命令行入口
+//! ## 推理程序的命令行入口
 
-use super::{Atom, KB, ReasoningError};
-use crate::bc::bc;
-use clap::{ArgAction, Parser};
+use super::{Atom, KB, ReasoningError, Theta};
+use crate::bc::{Limit, Outcome, SearchConfig, Strategy, bc_proofs, bc_solutions};
+use crate::fc::fc;
+use crate::format::atom_from_json;
+use crate::parser::{atom_to_prolog, parse_atom};
+use crate::proof::ProofTree;
+use crate::repl::Repl;
+use clap::{ArgAction, Args, Parser, Subcommand, ValueEnum, builder::RangedU64ValueParser};
+use std::ops::ControlFlow;
+use std::process::ExitCode;
+use std::time::Duration;
 
 #[derive(Parser, Debug)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub struct Cli {
-    /// 为知识库传入JSON文件
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// 为知识库传入文件，`.pl`文件按类Prolog语法解析，其余按JSON解析
     #[arg(long = "kbfile", action = ArgAction::SetTrue)]
     pub kbfile: bool,
     /// 用于推理的已知条件知识库
-    pub knowledge_base: String,
-    /// 为待证命题传入JSON文件
+    #[arg(required = true)]
+    pub knowledge_base: Option<String>,
+    /// 为待证命题传入文件，`.pl`文件按类Prolog语法解析，其余按JSON解析
     #[arg(long = "file", action = ArgAction::SetTrue)]
     pub smfile: bool,
     /// 待证命题
-    pub statement: String,
+    #[arg(required = true)]
+    pub statement: Option<String>,
     /// 显示详细证明过程
     #[arg(long = "verbose", action = ArgAction::SetTrue)]
     pub verbose: bool,
+    /// 列出待证命题的所有解
+    #[arg(long = "all", action = ArgAction::SetTrue)]
+    pub all: bool,
+    /// 最多列出的解的个数
+    #[arg(long = "limit", value_name = "N", value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    pub limit: Option<usize>,
+    /// 以指定格式输出证明树
+    #[arg(long = "proof-format", value_enum)]
+    pub proof_format: Option<ProofFormat>,
+    /// 最大推理深度，默认为5；迭代加深时为深度上限，默认不设上限
+    #[arg(long = "max-depth", value_name = "N")]
+    pub max_depth: Option<usize>,
+    /// 使用迭代加深搜索，给出所需深度最浅的证明
+    ///
+    /// 从深度0开始逐步放宽深度限制，直至找到证明或步数、时间用尽。
+    /// 未指定`--max-depth`时深度不设上限，应配合`--max-steps`或`--timeout`限制总的搜索量
+    #[arg(long = "iterative-deepening", action = ArgAction::SetTrue)]
+    pub iterative_deepening: bool,
+    /// 知识库为Datalog程序时自底向上求出与命题相关的事实后查表回答，否则照常使用反向链接
+    ///
+    /// Datalog程序即不含函数符号、且每条规则结论中的变量都出现在其条件中的知识库。
+    /// 求值前按待证命题做魔集改写，只推出与之相关的事实；
+    /// 求出的解总是完整的，不受深度、步数与时间限制。指定`--proof-format`时仍使用反向链接
+    #[arg(long = "datalog", action = ArgAction::SetTrue, conflicts_with = "iterative_deepening")]
+    pub datalog: bool,
+    /// 最大推理步数
+    #[arg(long = "max-steps", value_name = "N")]
+    pub max_steps: Option<usize>,
+    /// 推理时间上限（秒）
+    #[arg(long = "timeout", value_name = "SECONDS", value_parser = parse_timeout)]
+    pub timeout: Option<Duration>,
+    /// 合一时不做出现检查，推理更快，但`X = f(X)`之类的合一可能导致错误的证明
+    #[arg(long = "no-occurs-check", action = ArgAction::SetTrue)]
+    pub no_occurs_check: bool,
 }
 
-/// ## 使用反向链接的逻辑证明器
-pub fn prove(args: &Cli) -> Result<(), ReasoningError> {
-    let kb: KB;
-    if args.kbfile {
-        let data = std::fs::read_to_string(&args.knowledge_base)
-            .map_err(|_| ReasoningError::FileError(args.knowledge_base.clone()))?;
-        kb = serde_json::from_str(&data)?;
+/// 解析时间上限，要求为`Duration`能够表示的非负秒数
+fn parse_timeout(s: &str) -> Result<Duration, String> {
+    match s.parse::<f64>().map(Duration::try_from_secs_f64) {
+        Ok(Ok(timeout)) => Ok(timeout),
+        _ => Err(format!("`{s}`不是有效的秒数")),
+    }
+}
+
+/// ## 子命令
+#[derive(Subcommand, Debug)]
+#[command(about = None, long_about = None)]
+pub enum Command {
+    /// 将旧版JSON格式或类Prolog语法的知识库转换为当前JSON格式
+    Convert(ConvertArgs),
+    /// 载入知识库后进入交互式推理环境
+    Repl(ReplArgs),
+    /// 以前向链接求出知识库能推出的全部事实
+    Saturate(SaturateArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct ConvertArgs {
+    /// 待转换的知识库文件
+    pub input: String,
+    /// 输出文件，缺省时输出到标准输出
+    #[arg(short = 'o', long = "output")]
+    pub output: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct ReplArgs {
+    /// 知识库文件，缺省时从空知识库开始
+    pub knowledge_base: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct SaturateArgs {
+    /// 知识库文件
+    pub knowledge_base: String,
+    /// 最大迭代轮数，含函数符号的知识库可能推出无穷多个事实
+    #[arg(long = "max-rounds", value_name = "N", default_value_t = 10)]
+    pub max_rounds: usize,
+    /// 推出的事实数上限，达到后立即停止
+    #[arg(long = "max-facts", value_name = "N", default_value_t = 100000)]
+    pub max_facts: usize,
+}
+
+/// ## 证明树的输出格式
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProofFormat {
+    /// Graphviz DOT
+    Dot,
+    /// Mermaid流程图
+    Mermaid,
+    /// JSON
+    Json,
+}
+
+/// ## 待证命题的一个解
+pub struct Answer {
+    /// 待证命题中变量的绑定
+    pub bindings: Vec<Theta>,
+    /// 按`--proof-format`导出的证明树
+    pub proof: Option<String>,
+}
+
+/// ## 读取输入内容并判断其是否使用类Prolog语法
+/// 文件以`.pl`后缀区分，直接传入的字符串以是否以`{`开头区分
+fn read_input(source: &str, is_file: bool) -> Result<(String, bool), ReasoningError> {
+    if is_file {
+        let data = std::fs::read_to_string(source)
+            .map_err(|_| ReasoningError::FileError(source.to_string()))?;
+        Ok((data, source.ends_with(".pl")))
     } else {
-        kb = serde_json::from_str(&args.knowledge_base)?;
+        let prolog = !source.trim_start().starts_with('{');
+        Ok((source.to_string(), prolog))
+    }
+}
+
+/// ## 读取知识库
+/// `is_file`为真时`source`为文件路径，否则为知识库内容本身，读取后检查知识库（见`KB::check`）
+fn load_kb(source: &str, is_file: bool) -> Result<KB, ReasoningError> {
+    let (data, prolog) = read_input(source, is_file)?;
+    KB::read(&data, prolog)
+}
+
+/// ## 读取待证命题
+/// `is_file`为真时`source`为文件路径，否则为命题内容本身
+pub(crate) fn load_theorem(source: &str, is_file: bool) -> Result<Atom, ReasoningError> {
+    match read_input(source, is_file)? {
+        (data, true) => parse_atom(&data),
+        (data, false) => atom_from_json(&data),
     }
-    let theorem: Atom = if args.smfile {
-        let data = std::fs::read_to_string(&args.statement)
-            .map_err(|_| ReasoningError::FileError(args.statement.clone()))?;
-        serde_json::from_str(&data)?
+}
+
+/// 按指定格式导出证明树
+fn export_proof(proof: &ProofTree, format: ProofFormat, kb: &KB) -> String {
+    match format {
+        ProofFormat::Dot => proof.to_dot(kb),
+        ProofFormat::Mermaid => proof.to_mermaid(kb),
+        ProofFormat::Json => proof.to_json(),
+    }
+}
+
+/// ## 证明结果
+pub struct Report {
+    /// 按找到的顺序排列的解
+    pub answers: Vec<Answer>,
+    /// 搜索中触发的限制
+    pub limit: Option<Limit>,
+    /// 是否有分支因否定中含有未确定的变量而失败
+    pub floundered: bool,
+    /// 证明结果
+    pub outcome: Outcome,
+}
+
+/// 发生错误时的退出码，与命令行参数错误时一致
+pub const EXIT_ERROR: u8 = 2;
+
+impl Report {
+    /// ## 按证明结果给出退出码
+    /// 命题得证为0，穷尽搜索后无法得证为1，因触发限制未能得出结论为3，
+    /// 因否定中含有未确定的变量未能得出结论时与其他错误相同，为2
+    pub fn exit_code(&self) -> ExitCode {
+        match self.outcome {
+            Outcome::Proved => ExitCode::SUCCESS,
+            Outcome::Disproved => ExitCode::from(1),
+            Outcome::Unknown(_) => ExitCode::from(3),
+            Outcome::Floundered => ExitCode::from(EXIT_ERROR),
+        }
+    }
+}
+
+/// ## 使用反向链接的逻辑证明器
+/// 按找到的顺序返回各个解中待证命题变量的绑定，
+/// 指定了`--proof-format`时一并给出导出的证明树。
+/// 默认只求第一个解，`--all`求出所有解，`--limit N`至多求出N个解
+pub fn prove(args: &Cli) -> Result<Report, ReasoningError> {
+    let knowledge_base = args
+        .knowledge_base
+        .as_deref()
+        .expect("未使用子命令时必须给出知识库");
+    let statement = args
+        .statement
+        .as_deref()
+        .expect("未使用子命令时必须给出待证命题");
+    let kb = load_kb(knowledge_base, args.kbfile)?;
+    let theorem = load_theorem(statement, args.smfile)?;
+    let config = SearchConfig {
+        max_depth: args.max_depth.unwrap_or(if args.iterative_deepening {
+            usize::MAX
+        } else {
+            SearchConfig::default().max_depth
+        }),
+        max_steps: args.max_steps,
+        timeout: args.timeout,
+        strategy: if args.iterative_deepening {
+            Strategy::IterativeDeepening
+        } else if args.datalog {
+            Strategy::Datalog
+        } else {
+            Strategy::DepthFirst
+        },
+        occurs_check: !args.no_occurs_check,
+        verbose: args.verbose,
+    };
+    let limit = match (args.all, args.limit) {
+        (_, Some(n)) => Some(n),
+        (true, None) => None,
+        (false, None) => Some(1),
+    };
+    let mut answers = Vec::<Answer>::new();
+    let mut on_solution = |bindings: Vec<Theta>, proof: Option<ProofTree>| {
+        let proof = proof.zip(args.proof_format);
+        answers.push(Answer {
+            bindings,
+            proof: proof.map(|(proof, format)| export_proof(&proof, format, &kb)),
+        });
+        if limit.is_some_and(|n| answers.len() >= n) {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    };
+    let summary = if args.proof_format.is_some() {
+        bc_proofs(&kb, &theorem, &config, |bindings, proof| {
+            on_solution(bindings, Some(proof))
+        })
     } else {
-        serde_json::from_str(&args.statement)?
+        bc_solutions(&kb, &theorem, &config, |bindings| {
+            on_solution(bindings, None)
+        })
+    };
+    Ok(Report {
+        answers,
+        limit: summary.limit,
+        floundered: summary.floundered,
+        outcome: summary.outcome,
+    })
+}
+
+/// ## 将知识库文件转换为当前JSON格式
+/// 指定了输出文件时写入该文件，否则输出到标准输出
+pub fn convert(args: &ConvertArgs) -> Result<(), ReasoningError> {
+    let kb = KB::load(&args.input)?;
+    let json = serde_json::to_string_pretty(&kb)?;
+    match &args.output {
+        Some(output) => std::fs::write(output, json + "\n")
+            .map_err(|_| ReasoningError::WriteError(output.clone())),
+        None => {
+            println!("{json}");
+            Ok(())
+        }
+    }
+}
+
+/// ## 进入交互式推理环境
+pub fn repl(args: &ReplArgs) -> Result<(), ReasoningError> {
+    let kb = match &args.knowledge_base {
+        Some(path) => KB::load(path)?,
+        None => KB::default(),
     };
-    bc(&kb, &theorem, args.verbose, 5)?;
-    Ok(())
+    Repl::new(kb, std::io::stdin().lock(), std::io::stdout()).run()
+}
+
+/// ## 以前向链接求出知识库能推出的事实
+/// 按推出的顺序逐行输出知识库原有事实之外的新事实。
+/// 到达不动点时退出码为0，因达到最大迭代轮数或事实数上限而停止时为3
+pub fn saturate(args: &SaturateArgs) -> Result<ExitCode, ReasoningError> {
+    let kb = KB::load(&args.knowledge_base)?;
+    let saturation = fc(&kb, args.max_rounds, args.max_facts);
+    for atom in &saturation.derived {
+        println!("{}.", atom_to_prolog(atom));
+    }
+    let count = saturation.derived.len();
+    if saturation.fixpoint {
+        eprintln!(
+            "共推出{count}条事实，第{}轮迭代后到达不动点",
+            saturation.rounds
+        );
+        Ok(ExitCode::SUCCESS)
+    } else if count >= args.max_facts {
+        eprintln!("共推出{count}条事实，已达到事实数上限，可能还有未推出的事实");
+        Ok(ExitCode::from(3))
+    } else {
+        eprintln!(
+            "共推出{count}条事实，已达到最大迭代轮数{}，可能还有未推出的事实",
+            saturation.rounds
+        );
+        Ok(ExitCode::from(3))
+    }
 }
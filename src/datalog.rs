@@ -0,0 +1,516 @@
+//! ## Datalog求值
+//! 知识库不含函数符号，且每条规则结论中的变量都出现在其条件中（值域限制）时为Datalog程序，
+//! 只能推出有限多个不含变量的事实，自底向上求出全部事实后即可直接查表回答命题。
+//! 求值采用半朴素迭代：每轮以上一轮新得到的事实（增量）匹配规则的某一个条件，
+//! 在它之前的条件只匹配更早的事实，之后的条件匹配全部已知事实，使每种组合恰好匹配一次；
+//! 各条件按已确定取值的参数位置在哈希索引中查找匹配的事实（哈希连接）。
+//! 含否定条件的程序按层求值，否定条件只涉及更低的层，在肯定条件全部匹配后检查相应的事实是否不存在
+
+use super::{Atom, KB, Name, PredicateIndicator, Rule, Symbol, Theta, Variable};
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+/// 规则中的参数：常量，或变量在规则中的编号
+#[derive(Debug, Clone, Copy)]
+enum Term {
+    Const(Name),
+    Var(usize),
+}
+
+/// ## 编译后的规则条件
+/// 匹配时先按`positions`上的取值`key`在索引中查找事实，
+/// 再由`binds`绑定新出现的变量，由`checks`检查条件中重复出现的变量
+#[derive(Debug)]
+struct Pattern {
+    relation: PredicateIndicator,
+    /// 匹配此条件时已确定取值的参数位置
+    positions: Vec<usize>,
+    /// 上述参数位置的取值
+    key: Vec<Term>,
+    /// 首次出现的变量所在的参数位置与变量编号
+    binds: Vec<(usize, usize)>,
+    /// 在此条件中再次出现的变量所在的参数位置与变量编号
+    checks: Vec<(usize, usize)>,
+}
+
+/// ## 编译后的规则
+#[derive(Debug)]
+struct CompiledRule {
+    head: PredicateIndicator,
+    head_terms: Vec<Term>,
+    vars: usize,
+    /// 第i项为以第i个肯定条件匹配增量时各条件的匹配顺序：该条件最先匹配，其余按原顺序。
+    /// 元素为条件的原序号及其编译结果
+    bodies: Vec<Vec<(usize, Pattern)>>,
+    /// 否定条件的谓词与参数，其中的变量均已由肯定条件绑定
+    negative: Vec<(PredicateIndicator, Vec<Term>)>,
+}
+
+/// 哈希索引：若干参数位置上的取值到具有这些取值的事实序号
+type Index = HashMap<Box<[Name]>, Vec<usize>>;
+
+/// ## 一个谓词的全部事实
+/// 事实按推出的顺序编号，索引中的序号因此递增排列，可按序号范围截取某一轮之前或之内的事实
+#[derive(Debug, Default)]
+struct Relation {
+    tuples: Vec<Box<[Name]>>,
+    set: HashSet<Box<[Name]>>,
+    /// 按参数位置组合建立的哈希索引
+    indexes: HashMap<Vec<usize>, Index>,
+}
+
+impl Relation {
+    fn insert(&mut self, tuple: Box<[Name]>) {
+        if self.set.contains(&tuple) {
+            return;
+        }
+        let n = self.tuples.len();
+        for (positions, index) in self.indexes.iter_mut() {
+            let key = positions.iter().map(|&k| tuple[k]).collect();
+            index.entry(key).or_default().push(n);
+        }
+        self.set.insert(tuple.clone());
+        self.tuples.push(tuple);
+    }
+}
+
+/// ## 判断知识库是否为Datalog程序
+/// 即不含函数符号，且每条规则结论与否定条件中的变量都出现在其肯定条件中
+pub(crate) fn is_datalog(kb: &KB) -> bool {
+    kb.rules.iter().all(|rule| {
+        let (positive, negative) = rule.split_condition();
+        let function_free = positive
+            .iter()
+            .copied()
+            .chain(negative.iter().copied())
+            .chain([&rule.conclusion])
+            .all(|atom| atom.args.iter().all(|arg| !matches!(arg, Symbol::Func(..))));
+        let mut condition_vars = Vec::<Symbol>::new();
+        for atom in positive {
+            for arg in &atom.args {
+                arg.collect_vars(&mut condition_vars);
+            }
+        }
+        function_free
+            && negative
+                .into_iter()
+                .chain([&rule.conclusion])
+                .flat_map(Atom::vars)
+                .all(|v| condition_vars.contains(&v))
+    })
+}
+
+/// ## 自底向上求出的全部事实
+#[derive(Debug)]
+pub(crate) struct Database {
+    relations: HashMap<PredicateIndicator, Relation>,
+    /// 求值进行的轮数
+    rounds: usize,
+}
+
+impl Database {
+    /// ## 求出Datalog程序的全部事实
+    /// 知识库不是Datalog程序时返回None
+    pub(crate) fn new(kb: &KB) -> Option<Database> {
+        if !is_datalog(kb) {
+            return None;
+        }
+        let mut database = Database {
+            relations: HashMap::new(),
+            rounds: 0,
+        };
+        // 先建立各条件所需的索引，之后加入的事实随之记入索引。规则按结论谓词所在的层分组
+        let strata = kb.strata();
+        let mut rules = Vec::<Vec<CompiledRule>>::new();
+        for rule in kb.rules.iter().filter(|rule| !rule.condition.is_empty()) {
+            let stratum = strata[&rule.conclusion.indicator()];
+            if rules.len() <= stratum {
+                rules.resize_with(stratum + 1, Vec::new);
+            }
+            rules[stratum].push(database.compile(rule));
+        }
+        for rule in kb.rules.iter().filter(|rule| rule.condition.is_empty()) {
+            let tuple = rule
+                .conclusion
+                .args
+                .iter()
+                .map(|arg| match arg {
+                    Symbol::Val(name) => *name,
+                    _ => unreachable!("Datalog程序中无条件的规则不含变量与函数符号"),
+                })
+                .collect();
+            database
+                .relations
+                .entry(rule.conclusion.indicator())
+                .or_default()
+                .insert(tuple);
+        }
+        for stratum in &rules {
+            database.evaluate(stratum);
+        }
+        Some(database)
+    }
+
+    /// 事实总数
+    pub(crate) fn len(&self) -> usize {
+        self.relations
+            .values()
+            .map(|relation| relation.tuples.len())
+            .sum()
+    }
+
+    pub(crate) fn rounds(&self) -> usize {
+        self.rounds
+    }
+
+    /// 编译一条规则，为其条件建立所需的索引
+    fn compile(&mut self, rule: &Rule) -> CompiledRule {
+        let (condition, negative) = rule.split_condition();
+        let conclusion = &rule.conclusion;
+        let mut vars = Vec::<Variable>::new();
+        let mut slot = |var: &Variable| match vars.iter().position(|v| v == var) {
+            Some(i) => i,
+            None => {
+                vars.push(*var);
+                vars.len() - 1
+            }
+        };
+        let atoms: Vec<Vec<Term>> = condition
+            .iter()
+            .copied()
+            .chain([conclusion])
+            .chain(negative.iter().copied())
+            .map(|atom| {
+                atom.args
+                    .iter()
+                    .map(|arg| match arg {
+                        Symbol::Val(name) => Term::Const(*name),
+                        Symbol::Var(var) => Term::Var(slot(var)),
+                        Symbol::Func(..) => unreachable!("Datalog程序不含函数符号"),
+                    })
+                    .collect()
+            })
+            .collect();
+        let (body, rest) = atoms.split_at(condition.len());
+        let (head_terms, negative_terms) = rest.split_first().unwrap();
+        let bodies = (0..condition.len())
+            .map(|first| {
+                let order = [first]
+                    .into_iter()
+                    .chain((0..condition.len()).filter(|&j| j != first));
+                let mut bound = vec![false; vars.len()];
+                order
+                    .map(|j| {
+                        let relation = condition[j].indicator();
+                        let pattern = self.pattern(relation, &body[j], &mut bound);
+                        (j, pattern)
+                    })
+                    .collect()
+            })
+            .collect();
+        CompiledRule {
+            head: conclusion.indicator(),
+            head_terms: head_terms.clone(),
+            vars: vars.len(),
+            bodies,
+            negative: negative
+                .iter()
+                .zip(negative_terms)
+                .map(|(atom, terms)| (atom.indicator(), terms.clone()))
+                .collect(),
+        }
+    }
+
+    /// 按此前已绑定的变量编译一个条件，并将其中新出现的变量记为已绑定
+    fn pattern(
+        &mut self,
+        relation: PredicateIndicator,
+        terms: &[Term],
+        bound: &mut [bool],
+    ) -> Pattern {
+        let mut pattern = Pattern {
+            relation,
+            positions: Vec::new(),
+            key: Vec::new(),
+            binds: Vec::new(),
+            checks: Vec::new(),
+        };
+        for (k, term) in terms.iter().enumerate() {
+            match *term {
+                Term::Var(v) if !bound[v] => {
+                    if pattern.binds.iter().any(|&(_, w)| w == v) {
+                        pattern.checks.push((k, v));
+                    } else {
+                        pattern.binds.push((k, v));
+                    }
+                }
+                _ => {
+                    pattern.positions.push(k);
+                    pattern.key.push(*term);
+                }
+            }
+        }
+        for &(_, v) in &pattern.binds {
+            bound[v] = true;
+        }
+        let relation = self.relations.entry(relation).or_default();
+        if !pattern.positions.is_empty() {
+            relation
+                .indexes
+                .entry(pattern.positions.clone())
+                .or_default();
+        }
+        pattern
+    }
+
+    /// ## 半朴素迭代直至不再产生新事实
+    /// `rules`为同一层的规则，更低的层已求值完毕。只有否定条件的规则不参与迭代，在开始前应用一次
+    fn evaluate(&mut self, rules: &[CompiledRule]) {
+        for rule in rules.iter().filter(|rule| rule.bodies.is_empty()) {
+            if self.absent(&rule.negative, &[]) {
+                let tuple = rule
+                    .head_terms
+                    .iter()
+                    .map(|term| value(term, &[]))
+                    .collect();
+                self.relations.entry(rule.head).or_default().insert(tuple);
+            }
+        }
+        // 上一轮开始时各谓词的事实个数，此后的事实为上一轮新得到的增量
+        let mut old = HashMap::<PredicateIndicator, usize>::new();
+        loop {
+            let current: HashMap<PredicateIndicator, usize> = self
+                .relations
+                .iter()
+                .map(|(indicator, relation)| (*indicator, relation.tuples.len()))
+                .collect();
+            if current
+                .iter()
+                .all(|(indicator, n)| old.get(indicator).copied().unwrap_or(0) == *n)
+            {
+                return;
+            }
+            self.rounds += 1;
+            let mut derived = Vec::<(PredicateIndicator, Box<[Name]>)>::new();
+            for rule in rules {
+                for (i, body) in rule.bodies.iter().enumerate() {
+                    let start = |j: usize| old.get(&body[j].1.relation).copied().unwrap_or(0);
+                    let end = |j: usize| current[&body[j].1.relation];
+                    if start(0) == end(0) {
+                        continue;
+                    }
+                    let ranges: Vec<Range<usize>> = (0..body.len())
+                        .map(|j| match body[j].0.cmp(&i) {
+                            std::cmp::Ordering::Equal => start(j)..end(j),
+                            std::cmp::Ordering::Less => 0..start(j),
+                            std::cmp::Ordering::Greater => 0..end(j),
+                        })
+                        .collect();
+                    let mut env = vec![None; rule.vars];
+                    self.join(body, &ranges, &mut env, &mut |env| {
+                        if !self.absent(&rule.negative, env) {
+                            return;
+                        }
+                        let tuple = rule
+                            .head_terms
+                            .iter()
+                            .map(|term| value(term, env))
+                            .collect();
+                        derived.push((rule.head, tuple));
+                    });
+                }
+            }
+            old = current;
+            for (indicator, tuple) in derived {
+                self.relations.entry(indicator).or_default().insert(tuple);
+            }
+        }
+    }
+
+    /// 否定条件在变量取值下对应的事实是否均不存在
+    fn absent(&self, negative: &[(PredicateIndicator, Vec<Term>)], env: &[Option<Name>]) -> bool {
+        negative.iter().all(|(indicator, terms)| {
+            self.relations.get(indicator).is_none_or(|relation| {
+                let tuple: Box<[Name]> = terms.iter().map(|term| value(term, env)).collect();
+                !relation.set.contains(&tuple)
+            })
+        })
+    }
+
+    /// ## 依次匹配各条件
+    /// 第j个条件只匹配序号在`ranges[j]`内的事实，全部条件匹配后以变量的取值调用`found`
+    fn join(
+        &self,
+        body: &[(usize, Pattern)],
+        ranges: &[Range<usize>],
+        env: &mut [Option<Name>],
+        found: &mut dyn FnMut(&[Option<Name>]),
+    ) {
+        let Some(((_, pattern), rest)) = body.split_first() else {
+            found(env);
+            return;
+        };
+        let relation = &self.relations[&pattern.relation];
+        let range = ranges[0].clone();
+        let mut visit = |n: usize, env: &mut [Option<Name>]| {
+            let tuple = &relation.tuples[n];
+            for &(k, v) in &pattern.binds {
+                env[v] = Some(tuple[k]);
+            }
+            if pattern
+                .checks
+                .iter()
+                .all(|&(k, v)| env[v] == Some(tuple[k]))
+            {
+                self.join(rest, &ranges[1..], env, found);
+            }
+        };
+        if pattern.positions.is_empty() {
+            for n in range {
+                visit(n, env);
+            }
+        } else {
+            let key: Box<[Name]> = pattern.key.iter().map(|term| value(term, env)).collect();
+            let Some(matched) = relation.indexes[&pattern.positions].get(&key) else {
+                return;
+            };
+            let from = matched.partition_point(|&n| n < range.start);
+            let to = matched.partition_point(|&n| n < range.end);
+            for &n in &matched[from..to] {
+                visit(n, env);
+            }
+        }
+    }
+
+    /// ## 查表回答命题
+    /// 按事实推出的顺序给出命题中变量的绑定，各个解互不相同
+    pub(crate) fn query(&self, goal: &Atom) -> Vec<Vec<Theta>> {
+        let Some(relation) = self.relations.get(&goal.indicator()) else {
+            return Vec::new();
+        };
+        relation
+            .tuples
+            .iter()
+            .filter_map(|tuple| {
+                let mut bindings = Vec::<Theta>::new();
+                for (arg, name) in goal.args.iter().zip(tuple.iter()) {
+                    let value = Symbol::Val(*name);
+                    match arg {
+                        Symbol::Val(_) | Symbol::Func(..) if *arg != value => return None,
+                        Symbol::Var(_) => {
+                            match bindings.iter().find(|theta| theta.origin == *arg) {
+                                Some(theta) if theta.result != value => return None,
+                                Some(_) => {}
+                                None => bindings.push(Theta::new(arg.clone(), value).unwrap()),
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                Some(bindings)
+            })
+            .collect()
+    }
+}
+
+/// 变量已绑定时的取值
+fn value(term: &Term, env: &[Option<Name>]) -> Name {
+    match *term {
+        Term::Const(name) => name,
+        Term::Var(v) => env[v].expect("按编译时的顺序匹配，变量在使用前已绑定"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_kb;
+
+    fn answers(database: &Database, goal: &str) -> Vec<String> {
+        database
+            .query(&Atom::parse(goal).unwrap())
+            .iter()
+            .map(|bindings| {
+                bindings
+                    .iter()
+                    .map(|theta| theta.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_is_datalog() {
+        assert!(is_datalog(
+            &parse_kb("path(X, Y) :- edge(X, Y). edge(a, b).").unwrap()
+        ));
+        // 含函数符号
+        assert!(!is_datalog(&parse_kb("nat(s(X)) :- nat(X).").unwrap()));
+        // 结论中的变量未出现在条件中
+        assert!(!is_datalog(&parse_kb("leq(X, X).").unwrap()));
+        assert!(!is_datalog(&parse_kb("p(X, Y) :- q(X).").unwrap()));
+        // 否定条件中的变量须出现在肯定条件中
+        assert!(is_datalog(&parse_kb("p(X) :- q(X), \\+ r(X).").unwrap()));
+        assert!(!is_datalog(
+            &parse_kb("p(X) :- q(X), \\+ r(X, Y).").unwrap()
+        ));
+    }
+
+    #[test]
+    fn test_datalog_transitive_closure() {
+        let kb = parse_kb(
+            "path(X, Z) :- path(X, Y), edge(Y, Z).
+             path(X, Y) :- edge(X, Y).
+             edge(a, b). edge(b, c). edge(c, a). edge(c, d).",
+        )
+        .unwrap();
+        let database = Database::new(&kb).unwrap();
+        let mut from_a = answers(&database, "path(a, X)");
+        from_a.sort();
+        assert_eq!(from_a, ["X = a", "X = b", "X = c", "X = d"]);
+        assert_eq!(answers(&database, "path(d, X)").len(), 0);
+        assert_eq!(answers(&database, "path(X, Y)").len(), 12);
+        assert_eq!(answers(&database, "path(X, X)").len(), 3);
+        assert_eq!(answers(&database, "path(c, d)"), [""]);
+    }
+
+    #[test]
+    fn test_datalog_negation() {
+        let kb = parse_kb(
+            "unreachable(X, Y) :- node(X), node(Y), \\+ path(X, Y).
+             path(X, Z) :- path(X, Y), edge(Y, Z).
+             path(X, Y) :- edge(X, Y).
+             quiet :- \\+ noisy.
+             node(a). node(b). node(c). edge(a, b). edge(b, a).",
+        )
+        .unwrap();
+        let database = Database::new(&kb).unwrap();
+        assert_eq!(
+            answers(&database, "unreachable(X, Y)"),
+            [
+                "X = a, Y = c",
+                "X = b, Y = c",
+                "X = c, Y = a",
+                "X = c, Y = b",
+                "X = c, Y = c"
+            ]
+        );
+        assert_eq!(answers(&database, "quiet"), [""]);
+    }
+
+    #[test]
+    fn test_datalog_repeated_vars_and_constants() {
+        let kb = parse_kb(
+            "same(X) :- pair(X, X).
+             likes_b(X) :- likes(X, b).
+             pair(a, a). pair(a, b). pair(c, c).
+             likes(x, b). likes(y, c).",
+        )
+        .unwrap();
+        let database = Database::new(&kb).unwrap();
+        assert_eq!(answers(&database, "same(X)"), ["X = a", "X = c"]);
+        assert_eq!(answers(&database, "likes_b(X)"), ["X = x"]);
+        assert_eq!(database.len(), 8);
+    }
+}
@@ -0,0 +1,123 @@
+//! ## 推理引擎
+//! 供其他程序嵌入的反向链接证明器：由知识库与搜索参数构造一次，之后可反复证明不同的命题
+
+use super::{Atom, KB, ReasoningError, Theta};
+use crate::bc::{SearchConfig, Solutions, Strategy, bc, bc_proof};
+use crate::fc::{Saturation, fc};
+use crate::proof::ProofTree;
+use std::time::Duration;
+
+/// ## 推理引擎
+/// 持有知识库与搜索参数，由`Engine::builder`配置后构造
+#[derive(Debug, Clone)]
+pub struct Engine {
+    kb: KB,
+    config: SearchConfig,
+}
+
+impl Engine {
+    /// ## 以默认的搜索参数构造推理引擎
+    /// 同名谓词的参数个数不一致时返回`ArityMismatch`，知识库无法分层时返回`Unstratified`
+    pub fn new(kb: KB) -> Result<Engine, ReasoningError> {
+        Engine::builder().build(kb)
+    }
+
+    /// ## 配置搜索参数
+    pub fn builder() -> EngineBuilder {
+        EngineBuilder::default()
+    }
+
+    pub fn kb(&self) -> &KB {
+        &self.kb
+    }
+
+    pub fn config(&self) -> &SearchConfig {
+        &self.config
+    }
+
+    /// ## 证明命题，返回第一个解中待证命题变量的绑定
+    /// 未找到证明时，若搜索因触发限制而不完整则返回对应的错误，否则返回`ProofNotFound`
+    pub fn prove(&self, goal: &Atom) -> Result<Vec<Theta>, ReasoningError> {
+        bc(&self.kb, goal, &self.config)
+    }
+
+    /// ## 证明命题，同时给出第一个解的证明树
+    pub fn prove_with_tree(&self, goal: &Atom) -> Result<(Vec<Theta>, ProofTree), ReasoningError> {
+        bc_proof(&self.kb, goal, &self.config)
+    }
+
+    /// ## 按搜索顺序逐个枚举命题的解
+    /// 枚举器只在取下一个解时才继续搜索，可随时暂停或放弃；
+    /// 其`summary`给出到目前为止找到的解的个数与触发的限制
+    pub fn solutions(&self, goal: &Atom) -> Solutions<'_> {
+        Solutions::new(&self.kb, goal, &self.config, false)
+    }
+
+    /// ## 逐个枚举命题的解及其证明树
+    pub fn proofs(&self, goal: &Atom) -> Solutions<'_> {
+        Solutions::new(&self.kb, goal, &self.config, true)
+    }
+
+    /// ## 以前向链接求出知识库能推出的事实
+    /// 至多进行`max_rounds`轮，推出`max_facts`条事实后停止；搜索参数不影响前向链接
+    pub fn saturate(&self, max_rounds: usize, max_facts: usize) -> Saturation {
+        fc(&self.kb, max_rounds, max_facts)
+    }
+}
+
+/// ## 推理引擎的搜索参数
+#[derive(Debug, Clone, Default)]
+pub struct EngineBuilder {
+    config: SearchConfig,
+    max_depth: Option<usize>,
+}
+
+impl EngineBuilder {
+    /// 最大推理深度。默认为5，迭代加深时默认不设上限
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// 每次证明的最大推理步数，默认不设上限
+    pub fn max_steps(mut self, max_steps: usize) -> Self {
+        self.config.max_steps = Some(max_steps);
+        self
+    }
+
+    /// 每次证明的时间上限，默认不设上限
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.config.timeout = Some(timeout);
+        self
+    }
+
+    /// 搜索策略，默认为深度优先
+    pub fn strategy(mut self, strategy: Strategy) -> Self {
+        self.config.strategy = strategy;
+        self
+    }
+
+    /// 合一时是否进行出现检查，默认进行
+    pub fn occurs_check(mut self, occurs_check: bool) -> Self {
+        self.config.occurs_check = occurs_check;
+        self
+    }
+
+    /// 是否向标准输出与标准错误打印详细证明过程，默认不打印
+    pub fn trace(mut self, trace: bool) -> Self {
+        self.config.verbose = trace;
+        self
+    }
+
+    /// ## 以给定的知识库构造推理引擎
+    /// 同名谓词的参数个数不一致时返回`ArityMismatch`，知识库无法分层时返回`Unstratified`。
+    pub fn build(self, kb: KB) -> Result<Engine, ReasoningError> {
+        kb.check()?;
+        let mut config = self.config;
+        config.max_depth = self.max_depth.unwrap_or(match config.strategy {
+            Strategy::DepthFirst | Strategy::Datalog => SearchConfig::default().max_depth,
+            Strategy::IterativeDeepening => usize::MAX,
+        });
+        Ok(Engine { kb, config })
+    }
+}
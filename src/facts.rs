@@ -0,0 +1,131 @@
+//! ## 基础事实库
+//! 保存不含变量的事实：知识库中无条件的常量事实，以及推理中得证的引理。
+//! 完全确定的命题按哈希直接查找；对知识库中的事实另按谓词与各个参数建立索引，
+//! 供部分参数已确定的命题查找可能与之合一的事实
+
+use super::{Atom, KB, PredicateIndicator, Symbol};
+use crate::proof::ProofTree;
+use std::collections::HashMap;
+
+/// 一条基础事实
+pub(crate) struct Fact {
+    pub(crate) atom: Atom,
+    /// 构造证明树时记录的证明
+    pub(crate) proof: Option<ProofTree>,
+    /// 来自知识库时为其规则序号，推理中得证的引理为None
+    pub(crate) rule: Option<usize>,
+}
+
+/// ## 基础事实库
+#[derive(Default)]
+pub(crate) struct FactStore {
+    facts: Vec<Fact>,
+    /// 事实到其序号
+    exact: HashMap<Atom, usize>,
+    /// 知识库中每个谓词的事实序号
+    by_predicate: HashMap<PredicateIndicator, Vec<usize>>,
+    /// 知识库中按谓词、参数位置与参数取值分组的事实序号
+    by_arg: HashMap<(PredicateIndicator, usize, Symbol), Vec<usize>>,
+}
+
+impl FactStore {
+    /// 收录知识库中的常量事实，`build_tree`为真时为每条事实记录证明
+    pub(crate) fn new(kb: &KB, build_tree: bool) -> Self {
+        let mut store = FactStore::default();
+        for (i, rule) in kb.rules.iter().enumerate() {
+            if rule.is_fact() && store.get(&rule.conclusion).is_none() {
+                let proof = build_tree.then(|| ProofTree::leaf(rule.conclusion.clone(), i));
+                let n = store.insert(rule.conclusion.clone(), proof, Some(i));
+                let indicator = rule.conclusion.indicator();
+                for (k, arg) in rule.conclusion.args.iter().enumerate() {
+                    store
+                        .by_arg
+                        .entry((indicator, k, arg.clone()))
+                        .or_default()
+                        .push(n);
+                }
+                store.by_predicate.entry(indicator).or_default().push(n);
+            }
+        }
+        store
+    }
+
+    fn insert(&mut self, atom: Atom, proof: Option<ProofTree>, rule: Option<usize>) -> usize {
+        let n = self.facts.len();
+        self.exact.insert(atom.clone(), n);
+        self.facts.push(Fact { atom, proof, rule });
+        n
+    }
+
+    /// ## 查找与命题完全相同的事实
+    pub(crate) fn get(&self, atom: &Atom) -> Option<&Fact> {
+        self.exact.get(atom).map(|&n| &self.facts[n])
+    }
+
+    /// ## 记录推理中得证的引理
+    /// 引理不含变量且此前未知时才会记录
+    pub(crate) fn learn(&mut self, atom: Atom, proof: Option<ProofTree>) {
+        if !atom.contains_var() && !self.exact.contains_key(&atom) {
+            self.insert(atom, proof, None);
+        }
+    }
+
+    /// ## 找出知识库中可能与命题合一的事实
+    /// 在命题已确定的参数中选取对应事实最少的一个缩小范围，没有确定的参数时返回该谓词的全部事实。
+    /// 结果按规则序号排列
+    pub(crate) fn matching(&self, atom: &Atom) -> impl Iterator<Item = &Fact> {
+        let indicator = atom.indicator();
+        let mut candidates = self.by_predicate.get(&indicator);
+        for (k, arg) in atom.args.iter().enumerate() {
+            if arg.contains_var() {
+                continue;
+            }
+            let facts = self.by_arg.get(&(indicator, k, arg.clone()));
+            let Some(facts) = facts else {
+                candidates = None;
+                break;
+            };
+            if candidates.is_none_or(|c| facts.len() < c.len()) {
+                candidates = Some(facts);
+            }
+        }
+        candidates.into_iter().flatten().map(|&n| &self.facts[n])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{parse_atom, parse_kb};
+
+    #[test]
+    fn test_fact_store() {
+        let kb = parse_kb(
+            "edge(a, b). edge(a, c). edge(b, c). edge(c, d). path(X, Y) :- edge(X, Y). node(a).",
+        )
+        .unwrap();
+        let mut store = FactStore::new(&kb, false);
+        let matching = |store: &FactStore, src: &str| -> Vec<usize> {
+            store
+                .matching(&parse_atom(src).unwrap())
+                .map(|fact| fact.rule.unwrap())
+                .collect()
+        };
+        assert_eq!(matching(&store, "edge(a, X)"), vec![0, 1]);
+        assert_eq!(matching(&store, "edge(X, c)"), vec![1, 2]);
+        assert_eq!(matching(&store, "edge(b, c)"), vec![2]);
+        assert_eq!(matching(&store, "edge(X, Y)"), vec![0, 1, 2, 3]);
+        assert_eq!(matching(&store, "edge(d, X)"), Vec::<usize>::new());
+        assert_eq!(matching(&store, "path(a, X)"), Vec::<usize>::new());
+        assert_eq!(
+            store.get(&parse_atom("edge(c, d)").unwrap()).unwrap().rule,
+            Some(3)
+        );
+        let lemma = parse_atom("path(a, b)").unwrap();
+        store.learn(lemma.clone(), None);
+        store.learn(parse_atom("path(a, X)").unwrap(), None);
+        assert_eq!(store.get(&lemma).unwrap().rule, None);
+        assert!(store.get(&parse_atom("path(a, X)").unwrap()).is_none());
+        assert_eq!(matching(&store, "path(a, X)"), Vec::<usize>::new());
+    }
+}
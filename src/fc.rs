@@ -0,0 +1,325 @@
+//! ## 前向链接
+//! 按《人工智能现代方法》中的FOL-FC-ASK，从知识库中的事实出发逐轮应用规则：
+//! 每轮找出条件全部能由已知事实满足的规则实例，将其结论中尚未出现过的事实加入已知事实，
+//! 直至某一轮不再产生新事实（不动点）。
+//! 含函数符号的知识库（如`examples/math`）可能推出无穷多个事实，因此另设迭代轮数与推出事实数的上限。
+//! 含否定条件的知识库按层求值：各层依次迭代至不动点，否定条件只涉及更低的层，
+//! 其中的命题与任何已知事实都无法合一时成立
+
+use super::{Atom, KB, Name, PredicateIndicator, Rule, Symbol, Variable};
+use crate::bindings::Bindings;
+use crate::unify::{exhaust_subst, unify};
+use std::collections::{HashMap, HashSet};
+use std::ops::ControlFlow;
+
+/// ## 前向链接的结果
+#[derive(Debug, Clone, PartialEq)]
+pub struct Saturation {
+    /// 知识库原有事实之外推出的新事实，按推出的顺序排列
+    pub derived: Vec<Atom>,
+    /// 进行的迭代轮数
+    pub rounds: usize,
+    /// 是否到达不动点；为假时因达到轮数或事实数上限而停止，可能还有未推出的事实
+    pub fixpoint: bool,
+}
+
+/// ## 已知事实
+/// 按谓词分组以便匹配规则条件。
+/// 含变量的事实表示其所有实例，与已有事实仅在变量命名上不同时视为重复：
+/// 判断重复时将变量依次改名为同一名称下的0、1、2……，使互为变体的事实得到相同的形式
+struct Facts {
+    atoms: Vec<Atom>,
+    /// 已知事实统一变量命名后的形式
+    seen: HashSet<Atom>,
+    by_predicate: HashMap<PredicateIndicator, Vec<usize>>,
+    /// 统一命名所用的变量名
+    anonymous: Name,
+}
+
+impl Facts {
+    fn new() -> Self {
+        Facts {
+            atoms: Vec::new(),
+            seen: HashSet::new(),
+            by_predicate: HashMap::new(),
+            anonymous: Name::new("_"),
+        }
+    }
+
+    fn canonical(&self, atom: &Atom) -> Atom {
+        rename_vars(atom, |_, k| Variable {
+            name: self.anonymous,
+            id: k,
+        })
+    }
+
+    fn contains(&self, atom: &Atom) -> bool {
+        self.seen.contains(&self.canonical(atom))
+    }
+
+    /// 加入一条事实，已有重复的事实时忽略
+    fn insert(&mut self, atom: Atom) {
+        if !self.seen.insert(self.canonical(&atom)) {
+            return;
+        }
+        self.by_predicate
+            .entry(atom.indicator())
+            .or_default()
+            .push(self.atoms.len());
+        self.atoms.push(atom);
+    }
+
+    /// ## 逐个给出满足全部条件的绑定
+    /// 依次将每个条件与同谓词的已知事实合一，含变量的事实先改名以免与规则中的变量混淆；
+    /// `found`返回`Break`时停止匹配
+    fn matches(
+        &self,
+        conditions: &[Atom],
+        bindings: &mut Bindings,
+        fresh: &mut usize,
+        found: &mut dyn FnMut(&Bindings, &mut usize) -> ControlFlow<()>,
+    ) -> ControlFlow<()> {
+        let Some((first, rest)) = conditions.split_first() else {
+            return found(bindings, fresh);
+        };
+        let Some(candidates) = self.by_predicate.get(&first.indicator()) else {
+            return ControlFlow::Continue(());
+        };
+        for &i in candidates {
+            let fact = &self.atoms[i];
+            let renamed;
+            let fact = if fact.contains_var() {
+                renamed = fact.rename_apart(fresh);
+                &renamed
+            } else {
+                fact
+            };
+            let mark = bindings.mark();
+            if unify(first, fact, bindings, true).is_ok() {
+                let flow = self.matches(rest, bindings, fresh, found);
+                bindings.undo(mark);
+                flow?;
+            }
+        }
+        ControlFlow::Continue(())
+    }
+
+    /// 是否有已知事实能与原子公式合一
+    fn unifiable(&self, atom: &Atom, fresh: &mut usize) -> bool {
+        self.matches(
+            std::slice::from_ref(atom),
+            &mut Bindings::new(),
+            fresh,
+            &mut |_, _| ControlFlow::Break(()),
+        )
+        .is_break()
+    }
+}
+
+/// ## 整理推出的事实中的变量
+/// 将变量编号归零，使其按知识库中的写法输出；名称相同的不同变量依次编号以示区分
+fn tidy(atom: &Atom) -> Atom {
+    let mut names = Vec::<Name>::new();
+    rename_vars(atom, |var, _| {
+        let id = names.iter().filter(|name| **name == var.name).count();
+        names.push(var.name);
+        Variable { id, ..*var }
+    })
+}
+
+/// ## 同时改名原子公式中的各个变量
+/// 每个不同的变量按首次出现的顺序调用一次`rename`，传入该变量及其序号
+fn rename_vars(atom: &Atom, mut rename: impl FnMut(&Variable, usize) -> Variable) -> Atom {
+    let mut renaming = Vec::<(Variable, Variable)>::new();
+    Atom {
+        predicate: atom.predicate,
+        args: atom
+            .args
+            .iter()
+            .map(|arg| rename_symbol(arg, &mut renaming, &mut rename))
+            .collect(),
+    }
+}
+
+fn rename_symbol(
+    x: &Symbol,
+    renaming: &mut Vec<(Variable, Variable)>,
+    rename: &mut impl FnMut(&Variable, usize) -> Variable,
+) -> Symbol {
+    match x {
+        Symbol::Var(var) => {
+            if let Some((_, renamed)) = renaming.iter().find(|(old, _)| old == var) {
+                return Symbol::Var(*renamed);
+            }
+            let renamed = rename(var, renaming.len());
+            renaming.push((*var, renamed));
+            Symbol::Var(renamed)
+        }
+        Symbol::Val(_) => x.clone(),
+        Symbol::Func(..) if !x.contains_var() => x.clone(),
+        Symbol::Func(name, args) => Symbol::Func(
+            *name,
+            args.iter()
+                .map(|arg| rename_symbol(arg, renaming, rename))
+                .collect(),
+        ),
+    }
+}
+
+/// ## 前向链接
+/// 对知识库做至多`max_rounds`轮前向链接，返回知识库中的事实之外推出的事实，
+/// 推出的事实达到`max_facts`条时立即停止。
+/// 每轮只使用此前各轮已知的事实，本轮推出的事实从下一轮起参与匹配。
+/// 规则按结论谓词所在的层分组，低层到达不动点后才开始求值高一层的规则；
+/// 否定条件在肯定条件全部满足后检查
+pub(crate) fn fc(kb: &KB, max_rounds: usize, max_facts: usize) -> Saturation {
+    let mut known = Facts::new();
+    for rule in &kb.rules {
+        if rule.condition.is_empty() {
+            known.insert(tidy(&rule.conclusion));
+        }
+    }
+    let given = known.atoms.len();
+    let strata = kb.strata();
+    let top = strata.values().copied().max().unwrap_or(0);
+    let mut fresh = 0;
+    let mut rounds = 0;
+    let mut fixpoint = false;
+    for stratum in 0..=top {
+        let rules: Vec<&Rule> = kb
+            .rules
+            .iter()
+            .filter(|rule| {
+                !rule.condition.is_empty() && strata[&rule.conclusion.indicator()] == stratum
+            })
+            .collect();
+        fixpoint = false;
+        while !fixpoint && rounds < max_rounds && known.atoms.len() - given < max_facts {
+            rounds += 1;
+            let mut new = Facts::new();
+            for rule in &rules {
+                fresh += 1;
+                let rule = KB::rule_standardize(rule, fresh);
+                let (positive, negative) = rule.split_condition();
+                let positive: Vec<Atom> = positive.into_iter().cloned().collect();
+                let flow = known.matches(
+                    &positive,
+                    &mut Bindings::new(),
+                    &mut fresh,
+                    &mut |bindings, fresh| {
+                        if negative
+                            .iter()
+                            .any(|atom| known.unifiable(&exhaust_subst(atom, bindings), fresh))
+                        {
+                            return ControlFlow::Continue(());
+                        }
+                        let conclusion = tidy(&exhaust_subst(&rule.conclusion, bindings));
+                        if !known.contains(&conclusion) {
+                            new.insert(conclusion);
+                        }
+                        if known.atoms.len() - given + new.atoms.len() >= max_facts {
+                            ControlFlow::Break(())
+                        } else {
+                            ControlFlow::Continue(())
+                        }
+                    },
+                );
+                if flow.is_break() {
+                    break;
+                }
+            }
+            fixpoint = new.atoms.is_empty();
+            for atom in new.atoms {
+                known.insert(atom);
+            }
+        }
+        if !fixpoint {
+            break;
+        }
+    }
+    Saturation {
+        derived: known.atoms.split_off(given),
+        rounds,
+        fixpoint,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_kb;
+    use crate::{pred, var};
+
+    fn derived(saturation: &Saturation) -> Vec<String> {
+        saturation
+            .derived
+            .iter()
+            .map(|atom| atom.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_fc_aima() {
+        let kb = parse_kb(
+            "criminal(X) :- american(X), weapon(Y), sells(X, Y, Z), hostile(Z).
+             sells(west, X, nono) :- missile(X), owns(nono, X).
+             weapon(X) :- missile(X).
+             hostile(X) :- enemy(X, america).
+             owns(nono, m1). missile(m1). american(west). enemy(nono, america).",
+        )
+        .unwrap();
+        let saturation = fc(&kb, 10, usize::MAX);
+        assert_eq!(
+            derived(&saturation),
+            [
+                "sells(west, m1, nono)",
+                "weapon(m1)",
+                "hostile(nono)",
+                "criminal(west)"
+            ]
+        );
+        assert_eq!(saturation.rounds, 3);
+        assert!(saturation.fixpoint);
+    }
+
+    #[test]
+    fn test_fc_round_limit() {
+        let kb = parse_kb("nat(zero). nat(s(X)) :- nat(X).").unwrap();
+        let saturation = fc(&kb, 3, usize::MAX);
+        assert_eq!(
+            derived(&saturation),
+            ["nat(s(zero))", "nat(s(s(zero)))", "nat(s(s(s(zero))))"]
+        );
+        assert!(!saturation.fixpoint);
+        let saturation = fc(&kb, 10, 2);
+        assert_eq!(saturation.derived.len(), 2);
+        assert!(!saturation.fixpoint);
+    }
+
+    #[test]
+    fn test_fc_negation() {
+        let kb = parse_kb(
+            "hostile(X) :- enemy(X, america), \\+ allied(X).
+             allied(X) :- treaty(X, america).
+             enemy(nono, america). enemy(wakanda, america). treaty(wakanda, america).",
+        )
+        .unwrap();
+        let saturation = fc(&kb, 10, usize::MAX);
+        // allied先到达不动点，之后才求值依赖其否定的hostile
+        assert_eq!(derived(&saturation), ["allied(wakanda)", "hostile(nono)"]);
+        assert_eq!(saturation.rounds, 4);
+        assert!(saturation.fixpoint);
+    }
+
+    #[test]
+    fn test_fc_facts_with_vars() {
+        let kb =
+            parse_kb("leq(X, X). leq(s(X), s(Y)) :- leq(X, Y). p(X, Y) :- leq(X, X).").unwrap();
+        let saturation = fc(&kb, 1, usize::MAX);
+        assert_eq!(derived(&saturation), ["leq(s(X), s(X))", "p(X, Y)"]);
+        // 编号不同的同名变量各自改名，不会混为一个变量
+        let renamed = pred("q", vec![var("x")]).rename_apart(&mut 5);
+        let atom = pred("q", vec![renamed.args[0].clone(), var("x"), var("x")]);
+        assert_eq!(tidy(&atom).to_string(), "q(X, X_1, X_1)");
+    }
+}
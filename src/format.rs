@@ -0,0 +1,147 @@
+//! ## 知识库的JSON文件格式
+//! 当前格式中原子公式形如`{"predicate": name, "args": [...]}`；
+//! 旧版格式中原子公式形如`{"Predicate": [name, [...]]}`，且无条件的事实单独列在`facts`中。
+//! 读取时自动识别两种格式，写出时总是使用当前格式
+
+use super::{Atom, KB, ReasoningError, Rule, Symbol};
+use crate::intern::Name;
+use serde::Deserialize;
+use serde_json::Value;
+
+/// ## JSON文件格式版本
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JsonFormat {
+    /// 旧版`{"Predicate": [name, args]}`格式
+    Legacy,
+    /// 当前`{"predicate": name, "args": args}`格式
+    Current,
+}
+
+/// 旧版格式的原子公式
+#[derive(Deserialize)]
+enum LegacyAtom {
+    Predicate(Name, Vec<Symbol>),
+}
+
+impl From<LegacyAtom> for Atom {
+    fn from(value: LegacyAtom) -> Self {
+        let LegacyAtom::Predicate(predicate, args) = value;
+        Atom { predicate, args }
+    }
+}
+
+/// 旧版格式的规则
+#[derive(Deserialize)]
+struct LegacyRule {
+    condition: Vec<LegacyAtom>,
+    conclusion: LegacyAtom,
+}
+
+/// 旧版格式的知识库
+#[derive(Deserialize)]
+struct LegacyKB {
+    rules: Vec<LegacyRule>,
+    #[serde(default)]
+    facts: Vec<LegacyAtom>,
+}
+
+impl From<LegacyKB> for KB {
+    fn from(value: LegacyKB) -> Self {
+        let mut rules: Vec<Rule> = value
+            .rules
+            .into_iter()
+            .map(|rule| Rule {
+                condition: rule
+                    .condition
+                    .into_iter()
+                    .map(|atom| Atom::from(atom).into())
+                    .collect(),
+                conclusion: rule.conclusion.into(),
+            })
+            .collect();
+        rules.extend(value.facts.into_iter().map(|fact| Rule {
+            condition: vec![],
+            conclusion: fact.into(),
+        }));
+        KB {
+            rules,
+            ..Default::default()
+        }
+    }
+}
+
+/// 判断JSON中是否含有旧版格式的原子公式
+fn contains_legacy_atom(value: &Value) -> bool {
+    match value {
+        Value::Object(map) => {
+            map.contains_key("Predicate") || map.values().any(contains_legacy_atom)
+        }
+        Value::Array(items) => items.iter().any(contains_legacy_atom),
+        _ => false,
+    }
+}
+
+/// ## 识别JSON的格式版本
+/// 含有`facts`字段或`Predicate`形式原子公式的为旧版格式，其余视为当前格式
+pub(crate) fn detect_format(value: &Value) -> JsonFormat {
+    if value.get("facts").is_some() || contains_legacy_atom(value) {
+        JsonFormat::Legacy
+    } else {
+        JsonFormat::Current
+    }
+}
+
+/// ## 从任一版本的JSON读取知识库
+/// 同时返回识别出的格式版本
+pub(crate) fn kb_from_json(data: &str) -> Result<(KB, JsonFormat), ReasoningError> {
+    let value: Value = serde_json::from_str(data)?;
+    let format = detect_format(&value);
+    let kb = match format {
+        JsonFormat::Legacy => serde_json::from_value::<LegacyKB>(value)?.into(),
+        JsonFormat::Current => serde_json::from_value(value)?,
+    };
+    Ok((kb, format))
+}
+
+/// ## 从任一版本的JSON读取原子公式
+pub(crate) fn atom_from_json(data: &str) -> Result<Atom, ReasoningError> {
+    let value: Value = serde_json::from_str(data)?;
+    match detect_format(&value) {
+        JsonFormat::Legacy => Ok(serde_json::from_value::<LegacyAtom>(value)?.into()),
+        JsonFormat::Current => Ok(serde_json::from_value(value)?),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{pred, val};
+
+    #[test]
+    fn test_legacy_kb() {
+        let (legacy, format) = kb_from_json(include_str!("../examples/AIMA/kb.json")).unwrap();
+        assert_eq!(format, JsonFormat::Legacy);
+        let (current, format) =
+            kb_from_json(include_str!("../examples/AIMA/knowledge_base.json")).unwrap();
+        assert_eq!(format, JsonFormat::Current);
+        assert_eq!(legacy.rules.len(), current.rules.len());
+        assert!(
+            legacy
+                .rules
+                .iter()
+                .any(|rule| rule.is_fact() && rule.conclusion == pred("missile", vec![val("m1")]))
+        );
+        let json = serde_json::to_string(&legacy).unwrap();
+        assert_eq!(kb_from_json(&json).unwrap().1, JsonFormat::Current);
+    }
+
+    #[test]
+    fn test_legacy_atom() {
+        let atom = atom_from_json(r#"{"Predicate": ["criminal", [{"Val": "west"}]]}"#).unwrap();
+        assert_eq!(atom, pred("criminal", vec![val("west")]));
+        assert!(matches!(
+            atom_from_json(r#"{"predicate": "criminal"}"#),
+            Err(ReasoningError::ParseError(_))
+        ));
+    }
+}
@@ -0,0 +1,114 @@
+//! ## 规则索引
+//! 按结论的谓词标识与第一个参数的主函子对知识库中的规则分组，
+//! 证明命题时只需标准化并尝试可能与之合一的规则。
+//! 无条件的常量事实由`facts`中的事实库索引，不在此列
+
+use super::{Atom, KB, PredicateIndicator, Symbol};
+use crate::intern::Name;
+use std::collections::HashMap;
+
+/// 第一个参数的主函子：常量名，或函数符号名与参数个数
+#[derive(Debug, PartialEq, Eq, Hash)]
+enum Functor {
+    Val(Name),
+    Func(Name, usize),
+}
+
+impl Functor {
+    /// 变量没有主函子
+    fn of(x: &Symbol) -> Option<Functor> {
+        match x {
+            Symbol::Var(_) => None,
+            Symbol::Val(name) => Some(Functor::Val(*name)),
+            Symbol::Func(name, args) => Some(Functor::Func(*name, args.len())),
+        }
+    }
+}
+
+/// 同一谓词的规则，各列表中的规则序号均按知识库中的顺序排列
+#[derive(Default)]
+struct Bucket {
+    /// 全部规则
+    all: Vec<usize>,
+    /// 结论的第一个参数为变量的规则，可与任何命题合一
+    unbound: Vec<usize>,
+    /// 按结论第一个参数的主函子分组的其余规则
+    by_functor: HashMap<Functor, Vec<usize>>,
+}
+
+/// ## 规则索引
+#[derive(Default)]
+pub(crate) struct RuleIndex {
+    buckets: HashMap<PredicateIndicator, Bucket>,
+}
+
+impl RuleIndex {
+    pub(crate) fn new(kb: &KB) -> Self {
+        let mut buckets = HashMap::<PredicateIndicator, Bucket>::new();
+        for (i, rule) in kb.rules.iter().enumerate() {
+            if rule.is_fact() {
+                continue;
+            }
+            let bucket = buckets.entry(rule.conclusion.indicator()).or_default();
+            bucket.all.push(i);
+            match rule.conclusion.args.first().and_then(Functor::of) {
+                Some(functor) => bucket.by_functor.entry(functor).or_default().push(i),
+                None => bucket.unbound.push(i),
+            }
+        }
+        RuleIndex { buckets }
+    }
+
+    /// ## 找出结论可能与命题合一的规则
+    /// 命题应已代入当前置换。返回的规则序号按知识库中的顺序排列
+    pub(crate) fn candidates(&self, theorem: &Atom) -> Vec<usize> {
+        let Some(bucket) = self.buckets.get(&theorem.indicator()) else {
+            return Vec::new();
+        };
+        let Some(functor) = theorem.args.first().and_then(Functor::of) else {
+            return bucket.all.clone();
+        };
+        let Some(matched) = bucket.by_functor.get(&functor) else {
+            return bucket.unbound.clone();
+        };
+        // 合并两个有序列表，保持规则原有的先后顺序
+        let mut candidates = Vec::<usize>::with_capacity(matched.len() + bucket.unbound.len());
+        let (mut i, mut j) = (0, 0);
+        while i < matched.len() && j < bucket.unbound.len() {
+            if matched[i] < bucket.unbound[j] {
+                candidates.push(matched[i]);
+                i += 1;
+            } else {
+                candidates.push(bucket.unbound[j]);
+                j += 1;
+            }
+        }
+        candidates.extend_from_slice(&matched[i..]);
+        candidates.extend_from_slice(&bucket.unbound[j..]);
+        candidates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{parse_atom, parse_kb};
+
+    #[test]
+    fn test_rule_index() {
+        let kb = parse_kb(
+            "leq(zero, X). leq(X, add(X, zero)). leq(add(W, X), add(Y, Z)) :- leq(W, Y), leq(X, Z).\n\
+             leq(seven, nine). leq(add(X, Y), add(Y, X)). leq(a, X). leq(b, X) :- p(X). p(seven).",
+        )
+        .unwrap();
+        let index = RuleIndex::new(&kb);
+        let candidates = |src: &str| index.candidates(&parse_atom(src).unwrap());
+        assert_eq!(candidates("leq(seven, Y)"), vec![1]);
+        assert_eq!(candidates("leq(add(a, b), Y)"), vec![1, 2, 4]);
+        assert_eq!(candidates("leq(add(a), Y)"), vec![1]);
+        assert_eq!(candidates("leq(b, Y)"), vec![1, 6]);
+        assert_eq!(candidates("leq(X, Y)"), vec![0, 1, 2, 4, 5, 6]);
+        assert_eq!(candidates("p(X)"), Vec::<usize>::new());
+        assert_eq!(candidates("geq(X, Y)"), Vec::<usize>::new());
+    }
+}
@@ -0,0 +1,278 @@
+//! ## 名称与逻辑项的驻留
+//! 谓词、常量、函数符号与变量的名称统一登记在全局名称表中，以登记顺序分配的整数编号表示，
+//! 比较、哈希与复制名称只涉及编号；由编号读取名称字符串无需加锁。序列化时仍写出名称字符串。
+//! 登记的名称在程序运行期间不会释放：名称表只增不减，反复登记大量不同的名称
+//! （如在交互式环境中不断加入含新常量的规则）会使内存占用持续增长。
+//!
+//! 函数符号的参数列表按结构驻留（hash-consing）在全局项表中：结构相同的参数列表总是同一个节点，
+//! 节点带有唯一的整数编号，比较与哈希参数列表只需比较编号，子项在所有包含它的项之间共享。
+//! 项表只以弱引用登记节点，不再被引用的节点随最后一个引用一同从项表中移除
+
+use crate::Symbol;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::fmt::{Debug, Display};
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock, Mutex, MutexGuard, OnceLock, RwLock, Weak};
+
+/// 名称字符串到编号的索引，登记新名称时加写锁
+static NAME_IDS: LazyLock<RwLock<HashMap<&'static str, u32>>> = LazyLock::new(Default::default);
+
+/// 名称表的段数，第k段存放编号为`2^k-1`到`2^(k+1)-2`的名称
+const SEGMENTS: usize = 32;
+
+/// 按编号存放的名称字符串。各段在首次用到时分配，已写入的项不再改变，读取时无需加锁
+static NAME_STRS: [OnceLock<Box<[OnceLock<&'static str>]>>; SEGMENTS] =
+    [const { OnceLock::new() }; SEGMENTS];
+
+/// 编号所在的段与段内位置
+fn locate(id: u32) -> (usize, usize) {
+    let n = id as usize + 1;
+    let segment = n.ilog2() as usize;
+    (segment, n - (1 << segment))
+}
+
+/// ## 驻留的名称
+/// 相同的字符串总是得到相同的编号，因此按编号比较即可判断名称是否相同
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Name(u32);
+
+impl Name {
+    /// 登记名称并返回其编号，已登记的名称直接返回原有的编号
+    pub fn new(name: &str) -> Name {
+        if let Some(&id) = NAME_IDS.read().unwrap().get(name) {
+            return Name(id);
+        }
+        let mut ids = NAME_IDS.write().unwrap();
+        if let Some(&id) = ids.get(name) {
+            return Name(id);
+        }
+        let id = u32::try_from(ids.len())
+            .ok()
+            .filter(|&id| id < u32::MAX)
+            .expect("名称个数超出上限");
+        let name: &'static str = Box::leak(name.into());
+        let (segment, index) = locate(id);
+        let slots =
+            NAME_STRS[segment].get_or_init(|| (0..1 << segment).map(|_| OnceLock::new()).collect());
+        slots[index].set(name).expect("每个编号只登记一次");
+        ids.insert(name, id);
+        Name(id)
+    }
+
+    /// 名称的编号，按登记顺序由0开始分配
+    pub fn id(self) -> u32 {
+        self.0
+    }
+
+    pub fn as_str(self) -> &'static str {
+        let (segment, index) = locate(self.0);
+        NAME_STRS[segment]
+            .get()
+            .and_then(|slots| slots[index].get())
+            .expect("名称的编号均已登记")
+    }
+}
+
+impl Display for Name {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Debug for Name {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl Serialize for Name {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Name {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Ok(Name::new(&name))
+    }
+}
+
+/// 项表的分片数，各分片独立加锁
+const SHARDS: usize = 16;
+
+/// 项表分片，以参数列表的结构哈希值索引节点
+type Shard = Mutex<HashMap<u64, Vec<Weak<ArgsNode>>>>;
+
+/// 全局项表
+static ARGS: LazyLock<[Shard; SHARDS]> = LazyLock::new(Default::default);
+
+/// 下一个参数列表节点的编号
+static NEXT_ARGS_ID: AtomicU64 = AtomicU64::new(0);
+
+/// 哈希值所在的项表分片。节点析构时也需加锁，因此忽略锁的毒化
+fn shard(hash: u64) -> MutexGuard<'static, HashMap<u64, Vec<Weak<ArgsNode>>>> {
+    ARGS[hash as usize % SHARDS]
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
+/// 项表中的参数列表节点
+struct ArgsNode {
+    id: u64,
+    /// 参数列表的结构哈希值
+    hash: u64,
+    args: Box<[Symbol]>,
+}
+
+impl Drop for ArgsNode {
+    /// 从项表中移除已失效的节点。参数在锁释放后才析构，因此析构子项时不会重复加锁
+    fn drop(&mut self) {
+        let mut table = shard(self.hash);
+        if let Some(nodes) = table.get_mut(&self.hash) {
+            nodes.retain(|node| node.strong_count() > 0);
+            if nodes.is_empty() {
+                table.remove(&self.hash);
+            }
+        }
+    }
+}
+
+/// ## 驻留的参数列表
+/// 结构相同的参数列表总是同一个节点，按节点编号比较与哈希。
+/// 参数本身已驻留，因此驻留一个参数列表只需哈希与比较其各个参数，无需遍历整棵子树
+#[derive(Clone)]
+pub struct Args(Arc<ArgsNode>);
+
+impl Args {
+    /// 驻留参数列表，已有结构相同的参数列表时返回原有的节点
+    pub fn new(args: Vec<Symbol>) -> Args {
+        let mut hasher = DefaultHasher::new();
+        args.hash(&mut hasher);
+        let hash = hasher.finish();
+        // 比较时临时持有的其他节点须在锁释放后才析构
+        let mut visited = Vec::<Arc<ArgsNode>>::new();
+        let mut table = shard(hash);
+        let nodes = table.entry(hash).or_default();
+        for node in nodes.iter().filter_map(Weak::upgrade) {
+            if *node.args == *args {
+                return Args(node);
+            }
+            visited.push(node);
+        }
+        let node = Arc::new(ArgsNode {
+            id: NEXT_ARGS_ID.fetch_add(1, Ordering::Relaxed),
+            hash,
+            args: args.into_boxed_slice(),
+        });
+        nodes.retain(|node| node.strong_count() > 0);
+        nodes.push(Arc::downgrade(&node));
+        Args(node)
+    }
+
+    /// 节点的编号，结构相同的参数列表在存活期间编号相同
+    pub fn id(&self) -> u64 {
+        self.0.id
+    }
+}
+
+impl Deref for Args {
+    type Target = [Symbol];
+
+    fn deref(&self) -> &[Symbol] {
+        &self.0.args
+    }
+}
+
+impl From<Vec<Symbol>> for Args {
+    fn from(args: Vec<Symbol>) -> Self {
+        Args::new(args)
+    }
+}
+
+impl FromIterator<Symbol> for Args {
+    fn from_iter<I: IntoIterator<Item = Symbol>>(iter: I) -> Self {
+        Args::new(iter.into_iter().collect())
+    }
+}
+
+impl PartialEq for Args {
+    fn eq(&self, other: &Self) -> bool {
+        self.id() == other.id()
+    }
+}
+
+impl Eq for Args {}
+
+impl Hash for Args {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id().hash(state);
+    }
+}
+
+impl Debug for Args {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(&**self, f)
+    }
+}
+
+impl Serialize for Args {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (**self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Args {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Args::new(Vec::deserialize(deserializer)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{func, val, var};
+
+    #[test]
+    fn test_intern() {
+        let a = Name::new("leq");
+        assert_eq!(a, Name::new(&String::from("leq")));
+        assert_ne!(a, Name::new("geq"));
+        assert_eq!(a.as_str(), "leq");
+        assert_eq!(a.id(), Name::new("leq").id());
+        let json = serde_json::to_string(&a).unwrap();
+        assert_eq!(json, r#""leq""#);
+        assert_eq!(serde_json::from_str::<Name>(&json).unwrap(), a);
+        assert_eq!(locate(0), (0, 0));
+        assert_eq!(locate(1), (1, 0));
+        assert_eq!(locate(6), (2, 3));
+        assert_eq!(locate(7), (3, 0));
+    }
+
+    #[test]
+    fn test_hash_consing() {
+        let build = || func("add", vec![func("s", vec![val("zero")]), var("x")]);
+        let (Symbol::Func(_, a), Symbol::Func(_, b)) = (build(), build()) else {
+            unreachable!()
+        };
+        assert_eq!(a.id(), b.id());
+        assert!(Arc::ptr_eq(&a.0, &b.0));
+        assert_ne!(a, Args::new(vec![val("zero")]));
+        let json = serde_json::to_string(&a).unwrap();
+        assert_eq!(serde_json::from_str::<Args>(&json).unwrap(), a);
+        // 不再被引用的节点从项表中移除，之后重新构造得到新的节点
+        let args = Args::new(vec![val("hash_consing_test")]);
+        let (hash, id) = (args.0.hash, args.id());
+        drop(args);
+        assert!(!shard(hash).get(&hash).is_some_and(|nodes| {
+            nodes
+                .iter()
+                .any(|node| node.upgrade().is_some_and(|node| node.id == id))
+        }));
+        assert_ne!(Args::new(vec![val("hash_consing_test")]).id(), id);
+    }
+}
@@ -1,23 +1,51 @@
 //! ## 包含反向链接算法的一阶谓词逻辑实现
+//! 以`Engine`嵌入其他程序：由`KB`构造推理引擎，再以`pred`、`var`、`val`、`func`构造待证命题进行证明
 
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::fmt::Display;
 mod bc;
 #[cfg(any(test, feature = "benchmark"))]
 pub mod bench;
+mod bindings;
 pub mod cli;
+mod datalog;
+mod engine;
+mod facts;
+mod fc;
+mod format;
+mod index;
+mod intern;
+mod magic;
+mod parser;
+mod proof;
+mod repl;
 mod unify;
 
+pub use bc::{Limit, Outcome, SearchConfig, SearchSummary, Solution, Solutions, Strategy};
+pub use engine::{Engine, EngineBuilder};
+pub use fc::Saturation;
+pub use intern::{Args, Name};
+pub use proof::ProofTree;
+
 /// ## 错误类型
 #[derive(Debug)]
 pub enum ReasoningError {
     ThetaError,
     UnifyError,
     DepthLimitExceed,
+    StepLimitExceed,
+    Timeout,
     CycleProof,
     ProofNotFound,
-    ParseError,
+    ArityMismatch(String),
+    Unstratified(String),
+    Floundering,
+    ParseError(String),
+    SyntaxError(String),
     FileError(String),
+    WriteError(String),
+    IoError(String),
 }
 
 impl Display for ReasoningError {
@@ -32,15 +60,39 @@ impl Display for ReasoningError {
             ReasoningError::DepthLimitExceed => {
                 write!(f, "推理递归深度超限")
             }
+            ReasoningError::StepLimitExceed => {
+                write!(f, "推理步数超限")
+            }
+            ReasoningError::Timeout => {
+                write!(f, "推理超时")
+            }
             ReasoningError::CycleProof => {
                 write!(f, "发生循环论证")
             }
-            ReasoningError::ParseError => {
-                write!(f, "JSON格式错误")
+            ReasoningError::ArityMismatch(message) => {
+                write!(f, "参数个数不一致：{}", message)
+            }
+            ReasoningError::Unstratified(message) => {
+                write!(f, "知识库无法分层：{}", message)
+            }
+            ReasoningError::Floundering => {
+                write!(f, "否定的命题中含有未确定的变量")
+            }
+            ReasoningError::ParseError(message) => {
+                write!(f, "JSON格式错误：{}", message)
+            }
+            ReasoningError::SyntaxError(message) => {
+                write!(f, "语法错误：{}", message)
             }
             ReasoningError::FileError(name) => {
                 write!(f, "无法读取文件{}", name)
             }
+            ReasoningError::WriteError(name) => {
+                write!(f, "无法写入文件{}", name)
+            }
+            ReasoningError::IoError(message) => {
+                write!(f, "输入输出错误：{}", message)
+            }
             ReasoningError::ProofNotFound => {
                 write!(f, "未找到有效证明路径")
             }
@@ -50,31 +102,103 @@ impl Display for ReasoningError {
 
 impl std::error::Error for ReasoningError {}
 
+impl From<std::io::Error> for ReasoningError {
+    fn from(value: std::io::Error) -> Self {
+        ReasoningError::IoError(value.to_string())
+    }
+}
+
 impl From<serde_json::Error> for ReasoningError {
-    fn from(_value: serde_json::Error) -> Self {
-        ReasoningError::ParseError
+    fn from(value: serde_json::Error) -> Self {
+        ReasoningError::ParseError(value.to_string())
+    }
+}
+/// ## 变量
+/// 由变量名与编号组成。知识库与待证命题中书写的变量编号为0，
+/// 推理时改名得到的变量使用互不相同的正整数编号，因此不会与其他变量重名
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "VariableRepr", into = "VariableRepr")]
+pub struct Variable {
+    name: Name,
+    id: usize,
+}
+
+impl Variable {
+    /// 变量名
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+    /// 变量编号，知识库与待证命题中书写的变量为0
+    pub fn id(&self) -> usize {
+        self.id
+    }
+    /// 是否为匿名变量`_`
+    pub fn is_anonymous(&self) -> bool {
+        self.name().starts_with(ANONYMOUS_PREFIX)
+    }
+}
+
+/// 解析器为每个匿名变量`_`生成以此开头的变量名。书写的变量名不能含有`#`，因此不会与之重名
+pub(crate) const ANONYMOUS_PREFIX: &str = "_#";
+
+/// 变量的序列化形式。编号为0的变量只记录变量名，即`{"Var": "x"}`
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+enum VariableRepr {
+    Name(Name),
+    Renamed { name: Name, id: usize },
+}
+
+impl From<VariableRepr> for Variable {
+    fn from(value: VariableRepr) -> Self {
+        match value {
+            VariableRepr::Name(name) => Variable { name, id: 0 },
+            VariableRepr::Renamed { name, id } => Variable { name, id },
+        }
     }
 }
+
+impl From<Variable> for VariableRepr {
+    fn from(value: Variable) -> Self {
+        match value.id {
+            0 => VariableRepr::Name(value.name),
+            id => VariableRepr::Renamed {
+                name: value.name,
+                id,
+            },
+        }
+    }
+}
+
 /// ## 逻辑项
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-enum Symbol {
+/// 名称均为驻留的整数编号，函数符号的参数列表按结构驻留，结构相同的子项只有一份，
+/// 复制与比较逻辑项无需遍历整棵子树
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Symbol {
     /// 变量
-    Var(String),
+    Var(Variable),
     /// 常量
-    Val(String),
+    Val(Name),
     /// 函数符号
-    Func(String, Vec<Symbol>),
+    Func(Name, Args),
 }
 
 impl Symbol {
-    pub fn var(name: impl Into<String>) -> Self {
-        Symbol::Var(name.into())
+    pub fn var(name: impl AsRef<str>) -> Self {
+        Symbol::Var(Variable {
+            name: Name::new(name.as_ref()),
+            id: 0,
+        })
     }
-    pub fn val(name: impl Into<String>) -> Self {
-        Symbol::Val(name.into())
+    pub fn val(name: impl AsRef<str>) -> Self {
+        Symbol::Val(Name::new(name.as_ref()))
     }
-    pub fn func(name: impl Into<String>, args: Vec<Symbol>) -> Self {
-        Symbol::Func(name.into(), args)
+    /// 构造函数符号，没有参数时与解析器一致，得到同名的常量
+    pub fn func(name: impl AsRef<str>, args: Vec<Symbol>) -> Self {
+        if args.is_empty() {
+            return Symbol::val(name);
+        }
+        Symbol::Func(Name::new(name.as_ref()), args.into())
     }
     /// ## 判断符号中是否含有变量
     /// 更常见的用法是判断符号是否仅仅由常量和仅包含常量的函数组成，也即判断该方法是否返回false
@@ -83,7 +207,7 @@ impl Symbol {
             Self::Var(_) => true,
             Self::Val(_) => false,
             Self::Func(_, args) => {
-                for arg in args {
+                for arg in args.iter() {
                     if arg.contains_var() {
                         return true;
                     }
@@ -92,23 +216,47 @@ impl Symbol {
             }
         }
     }
+    /// 是否为匿名变量`_`
+    pub fn is_anonymous(&self) -> bool {
+        matches!(self, Self::Var(var) if var.is_anonymous())
+    }
+    /// ## 按出现顺序收集符号中的变量（不重复）
+    fn collect_vars(&self, vars: &mut Vec<Symbol>) {
+        match self {
+            Self::Var(_) => {
+                if !vars.contains(self) {
+                    vars.push(self.clone());
+                }
+            }
+            Self::Val(_) => {}
+            Self::Func(_, args) => {
+                for arg in args.iter() {
+                    arg.collect_vars(vars);
+                }
+            }
+        }
+    }
 }
 
 impl Display for Symbol {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Symbol::Var(name) => {
-                write!(f, "{}", name.to_uppercase())
+            Symbol::Var(Variable { name, id: 0 }) => {
+                write!(f, "{}", name.as_str().to_uppercase())
+            }
+            Symbol::Var(Variable { name, id }) => {
+                write!(f, "{}_{}", name.as_str().to_uppercase(), id)
             }
             Symbol::Val(name) => {
                 write!(f, "{}", name)
             }
             Symbol::Func(name, args) => {
                 write!(f, "{}(", name)?;
-                let mut args_iter = args.iter();
-                write!(f, "{}", args_iter.next().unwrap())?;
-                for arg in args_iter {
-                    write!(f, ",{arg}")?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{arg}")?;
                 }
                 write!(f, ")")
             }
@@ -116,19 +264,62 @@ impl Display for Symbol {
     }
 }
 
-/// 原子公式
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
-struct Atom {
-    predicate: String,
+/// ## 原子公式
+/// 由谓词名与参数组成，可由`pred`构造或由`Atom::parse`从类Prolog语法解析
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct Atom {
+    predicate: Name,
     args: Vec<Symbol>,
 }
 
 /// ## 判断原子公式中是否含有变量
 /// 更常见的用法是判断原子公式是否仅仅由常量和仅包含常量的函数组成，也即判断该方法是否返回false
 impl Atom {
-    fn contains_var(&self) -> bool {
+    pub fn contains_var(&self) -> bool {
         self.args.iter().any(|arg| arg.contains_var())
     }
+    /// ## 构造原子公式，同`pred`
+    pub fn new(predicate: impl AsRef<str>, args: Vec<Symbol>) -> Atom {
+        pred(predicate, args)
+    }
+    /// ## 解析类Prolog语法的原子公式，如`leq(X, add(three, nine))`
+    pub fn parse(source: &str) -> Result<Atom, ReasoningError> {
+        parser::parse_atom(source)
+    }
+    /// 谓词名
+    pub fn predicate(&self) -> &str {
+        self.predicate.as_str()
+    }
+    /// 参数
+    pub fn args(&self) -> &[Symbol] {
+        &self.args
+    }
+    /// ## 谓词标识
+    fn indicator(&self) -> PredicateIndicator {
+        PredicateIndicator {
+            predicate: self.predicate,
+            arity: self.args.len(),
+        }
+    }
+    /// ## 将原子公式中的变量改名为从未出现过的变量
+    /// 每个不同的变量各取一个新编号，`fresh`为已分配的最大编号
+    fn rename_apart(&self, fresh: &mut usize) -> Atom {
+        let mut renaming = bindings::Bindings::new();
+        for v in self.vars() {
+            let Symbol::Var(var) = v else { unreachable!() };
+            *fresh += 1;
+            renaming.bind(var, Symbol::Var(Variable { id: *fresh, ..var }));
+        }
+        unify::exhaust_subst(self, &renaming)
+    }
+    /// ## 按出现顺序列出原子公式中的变量（不重复）
+    fn vars(&self) -> Vec<Symbol> {
+        let mut vars = Vec::<Symbol>::new();
+        for arg in self.args.iter() {
+            arg.collect_vars(&mut vars);
+        }
+        vars
+    }
 }
 
 impl Display for Atom {
@@ -144,98 +335,388 @@ impl Display for Atom {
     }
 }
 
-/// ## 常量构造函数
+/// ## 变量构造函数
 #[inline]
-fn var(s: impl Into<String>) -> Symbol {
+pub fn var(s: impl AsRef<str>) -> Symbol {
     Symbol::var(s)
 }
-/// ## 变量构造函数
+/// ## 常量构造函数
 #[inline]
-fn val(s: impl Into<String>) -> Symbol {
+pub fn val(s: impl AsRef<str>) -> Symbol {
     Symbol::val(s)
 }
 /// ## 函数构造函数
 #[inline]
-fn func(name: impl Into<String>, args: Vec<Symbol>) -> Symbol {
+pub fn func(name: impl AsRef<str>, args: Vec<Symbol>) -> Symbol {
     Symbol::func(name, args)
 }
 
 /// ## 原子公式构造函数
-fn pred(name: impl Into<String>, args: Vec<Symbol>) -> Atom {
+pub fn pred(name: impl AsRef<str>, args: Vec<Symbol>) -> Atom {
     Atom {
-        predicate: name.into(),
+        predicate: Name::new(name.as_ref()),
         args,
     }
 }
 
+/// ## 否定构造函数
+/// 构造`\+ atom`，作为规则的条件时以有限失败求值：`atom`无法得证时成立
+pub fn not(atom: Atom) -> Literal {
+    Literal::Neg(atom)
+}
+
+/// ## 文字
+/// 规则的条件，为肯定的原子公式或其否定。原子公式可直接转换为肯定的文字，否定由`not`构造
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+#[serde(from = "LiteralRepr", into = "LiteralRepr")]
+pub enum Literal {
+    /// 原子公式得证时成立
+    Pos(Atom),
+    /// `\+ atom`，以有限失败求值：原子公式无法得证时成立
+    Neg(Atom),
+}
+
+/// 文字的序列化形式。肯定的文字即原子公式，否定写作`{"not": atom}`
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+enum LiteralRepr {
+    Neg { not: Atom },
+    Pos(Atom),
+}
+
+impl From<LiteralRepr> for Literal {
+    fn from(value: LiteralRepr) -> Self {
+        match value {
+            LiteralRepr::Neg { not } => Literal::Neg(not),
+            LiteralRepr::Pos(atom) => Literal::Pos(atom),
+        }
+    }
+}
+
+impl From<Literal> for LiteralRepr {
+    fn from(value: Literal) -> Self {
+        match value {
+            Literal::Neg(not) => LiteralRepr::Neg { not },
+            Literal::Pos(atom) => LiteralRepr::Pos(atom),
+        }
+    }
+}
+
+impl From<Atom> for Literal {
+    fn from(value: Atom) -> Self {
+        Literal::Pos(value)
+    }
+}
+
+impl Literal {
+    /// 文字中的原子公式，否定时为被否定的原子公式
+    pub fn atom(&self) -> &Atom {
+        match self {
+            Literal::Pos(atom) | Literal::Neg(atom) => atom,
+        }
+    }
+    /// 是否为否定
+    pub fn is_negative(&self) -> bool {
+        matches!(self, Literal::Neg(_))
+    }
+}
+
+impl Display for Literal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Literal::Pos(atom) => write!(f, "{atom}"),
+            Literal::Neg(atom) => write!(f, "\\+ {atom}"),
+        }
+    }
+}
+
 /// ## 规则（霍恩子句）
-/// 形如X^Y^Z=>W的语句。=>左侧为condition，右侧为conclusion
-#[derive(Debug, Serialize, Deserialize, Clone)]
-struct Rule {
-    pub condition: Vec<Atom>,
+/// 形如X^Y^Z=>W的语句。=>左侧为condition，右侧为conclusion，condition中可以含有否定
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Rule {
+    pub condition: Vec<Literal>,
     pub conclusion: Atom,
 }
 
+impl Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, condition) in self.condition.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ^ ")?;
+            }
+            write!(f, "{}", condition)?;
+        }
+        if !self.condition.is_empty() {
+            write!(f, " => ")?;
+        }
+        write!(f, "{}", self.conclusion)
+    }
+}
+
 impl Rule {
     /// ## 判断规则是否是无条件的常量事实
     pub fn is_fact(&self) -> bool {
         self.condition.is_empty() && !self.conclusion.contains_var()
     }
+    /// ## 将条件分为肯定条件与否定条件中被否定的原子公式
+    fn split_condition(&self) -> (Vec<&Atom>, Vec<&Atom>) {
+        let mut positive = Vec::<&Atom>::new();
+        let mut negative = Vec::<&Atom>::new();
+        for condition in &self.condition {
+            match condition {
+                Literal::Pos(atom) => positive.push(atom),
+                Literal::Neg(atom) => negative.push(atom),
+            }
+        }
+        (positive, negative)
+    }
 }
 
 /// ## 逻辑置换记号
 /// `Theta { origin, result }` 表示以`result`替换`origin`的一个逻辑置换。
 /// 其中`origin`必须为变量(`Symbol::Var`)，否则返回ThetaError
-#[derive(Debug, Clone)]
-struct Theta {
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Theta {
     origin: Symbol,
     result: Symbol,
 }
 
 impl Theta {
-    pub fn new(origin: Symbol, result: Symbol) -> Result<Self, ReasoningError> {
+    pub(crate) fn new(origin: Symbol, result: Symbol) -> Result<Self, ReasoningError> {
         match origin {
             Symbol::Var(_) => Ok(Theta { origin, result }),
             _ => Err(ReasoningError::ThetaError),
         }
     }
+    /// 被替换的变量
+    pub fn origin(&self) -> &Symbol {
+        &self.origin
+    }
+    /// 替换变量的项
+    pub fn result(&self) -> &Symbol {
+        &self.result
+    }
+}
+
+impl Display for Theta {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} = {}", self.origin, self.result)
+    }
+}
+
+/// ## 谓词标识
+/// 由谓词名与参数个数组成，写作`leq/2`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+struct PredicateIndicator {
+    predicate: Name,
+    arity: usize,
+}
+
+impl Display for PredicateIndicator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.predicate, self.arity)
+    }
 }
 
 /// ## 知识库
-/// 由规则rules组成
-#[derive(Serialize, Deserialize)]
-struct KB {
+/// 由规则rules组成，tabled中列出的谓词使用表格化求解
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct KB {
     rules: Vec<Rule>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tabled: Vec<PredicateIndicator>,
 }
 
 impl KB {
-    // 在变量后追加编号
+    /// ## 由规则构造知识库
+    pub fn new(rules: Vec<Rule>) -> KB {
+        KB {
+            rules,
+            tabled: Vec::new(),
+        }
+    }
+    /// ## 解析类Prolog语法的知识库
+    pub fn parse(source: &str) -> Result<KB, ReasoningError> {
+        parser::parse_kb(source)
+    }
+    /// ## 读取JSON格式的知识库，当前格式与旧版`Predicate`格式均可
+    pub fn from_json(source: &str) -> Result<KB, ReasoningError> {
+        Ok(format::kb_from_json(source)?.0)
+    }
+    /// ## 读取知识库文件
+    /// `.pl`文件按类Prolog语法解析，其余按JSON解析，读取后检查知识库（见`check`）
+    pub fn load(path: &str) -> Result<KB, ReasoningError> {
+        let data = std::fs::read_to_string(path)
+            .map_err(|_| ReasoningError::FileError(path.to_string()))?;
+        KB::read(&data, path.ends_with(".pl"))
+    }
+    /// ## 解析知识库内容并检查
+    /// `prolog`为真时按类Prolog语法解析，否则按JSON解析（当前格式与旧版`Predicate`格式均可）
+    pub(crate) fn read(source: &str, prolog: bool) -> Result<KB, ReasoningError> {
+        let kb = if prolog {
+            KB::parse(source)?
+        } else {
+            KB::from_json(source)?
+        };
+        kb.check()?;
+        Ok(kb)
+    }
+    /// ## 检查知识库
+    /// 即检查同名谓词的参数个数是否一致（见`check_arity`），以及知识库能否分层（见`check_negation`）
+    pub fn check(&self) -> Result<(), ReasoningError> {
+        self.check_arity()?;
+        self.check_negation()
+    }
+    /// 知识库中的全部规则
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+    /// 在知识库末尾添加一条规则
+    pub fn add_rule(&mut self, rule: Rule) {
+        self.rules.push(rule);
+    }
+    /// ## 声明谓词使用表格化求解
+    pub fn table(&mut self, predicate: impl AsRef<str>, arity: usize) {
+        let indicator = PredicateIndicator {
+            predicate: Name::new(predicate.as_ref()),
+            arity,
+        };
+        if !self.tabled.contains(&indicator) {
+            self.tabled.push(indicator);
+        }
+    }
+    // 将变量的编号设为i，不含变量的子项直接共享
     fn index_var(x: &Symbol, i: usize) -> Symbol {
         match x {
-            Symbol::Var(name) => var(format!("{name}{i}")),
-            Symbol::Func(name, args) => {
-                let mut new_args = Vec::<Symbol>::new();
-                for arg in args.iter() {
-                    new_args.push(KB::index_var(&arg.clone(), i))
-                }
-                func(name.clone(), new_args)
-            }
+            Symbol::Var(Variable { name, .. }) => Symbol::Var(Variable { name: *name, id: i }),
+            Symbol::Func(name, args) if x.contains_var() => Symbol::Func(
+                *name,
+                args.iter().map(|arg| KB::index_var(arg, i)).collect(),
+            ),
             _ => x.clone(),
         }
     }
-    // 为原子公式中每个变量追加统一编号
+    // 将原子公式中每个变量的编号统一设为i
     fn index_atom(x: &Atom, i: usize) -> Atom {
         Atom {
-            predicate: x.predicate.clone(),
+            predicate: x.predicate,
             args: x.args.iter().map(|arg| KB::index_var(arg, i)).collect(),
         }
     }
+    /// ## 判断原子公式的谓词是否使用表格化求解
+    fn is_tabled(&self, atom: &Atom) -> bool {
+        self.tabled.contains(&atom.indicator())
+    }
+    /// ## 检查同名谓词的参数个数是否一致
+    /// 谓词由名称与参数个数共同确定，同名而参数个数不同的谓词通常是笔误，
+    /// 发现时报告首次出现的两种用法及其所在规则的序号
+    pub fn check_arity(&self) -> Result<(), ReasoningError> {
+        let mut seen = Vec::<(PredicateIndicator, usize)>::new();
+        for (i, rule) in self.rules.iter().enumerate() {
+            let conditions = rule.condition.iter().map(Literal::atom);
+            for atom in conditions.chain([&rule.conclusion]) {
+                let indicator = atom.indicator();
+                match seen
+                    .iter()
+                    .find(|(p, _)| p.predicate == indicator.predicate)
+                {
+                    Some((p, j)) if p.arity != indicator.arity => {
+                        return Err(ReasoningError::ArityMismatch(format!(
+                            "第{j}条规则中为{p}，第{i}条规则中为{indicator}"
+                        )));
+                    }
+                    Some(_) => {}
+                    None => seen.push((indicator, i)),
+                }
+            }
+        }
+        Ok(())
+    }
+    /// ## 检查否定的用法
+    /// 谓词不能经由否定条件递归地依赖于自身（知识库须能分层），
+    /// 否则如`p :- \+ p.`，以有限失败求值否定时既不能证明也不能否定该谓词
+    pub fn check_negation(&self) -> Result<(), ReasoningError> {
+        let mut dependencies = HashMap::<PredicateIndicator, Vec<PredicateIndicator>>::new();
+        for rule in &self.rules {
+            dependencies
+                .entry(rule.conclusion.indicator())
+                .or_default()
+                .extend(
+                    rule.condition
+                        .iter()
+                        .map(|condition| condition.atom().indicator()),
+                );
+        }
+        for (i, rule) in self.rules.iter().enumerate() {
+            let head = rule.conclusion.indicator();
+            for condition in &rule.condition {
+                let Literal::Neg(negated) = condition else {
+                    continue;
+                };
+                // 从被否定的谓词出发沿依赖关系搜索，能回到结论的谓词即构成经由否定的递归
+                let mut visited = HashSet::from([negated.indicator()]);
+                let mut pending = vec![negated.indicator()];
+                while let Some(indicator) = pending.pop() {
+                    if indicator == head {
+                        return Err(ReasoningError::Unstratified(format!(
+                            "第{i}条规则中{head}经由否定条件{condition}依赖于自身"
+                        )));
+                    }
+                    for &next in dependencies.get(&indicator).into_iter().flatten() {
+                        if visited.insert(next) {
+                            pending.push(next);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+    /// ## 为谓词分层
+    /// 谓词所在的层不低于其规则中肯定条件的谓词，且高于否定条件的谓词，
+    /// 按层从低到高求值时，否定条件所需的事实总已在更低的层中全部求出。
+    /// 知识库须能分层（见`check_negation`）。结果包含每个规则结论的谓词，只在条件中出现的谓词位于第0层
+    fn strata(&self) -> HashMap<PredicateIndicator, usize> {
+        let mut strata: HashMap<PredicateIndicator, usize> = self
+            .rules
+            .iter()
+            .map(|rule| (rule.conclusion.indicator(), 0))
+            .collect();
+        // 能分层时层数不超过规则条数，以此保证不能分层的知识库也会停止
+        let limit = self.rules.len();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for rule in &self.rules {
+                let head = rule.conclusion.indicator();
+                let current = strata[&head];
+                let mut stratum = current;
+                for condition in &rule.condition {
+                    stratum = stratum.max(match condition {
+                        Literal::Neg(atom) => {
+                            strata.get(&atom.indicator()).copied().unwrap_or(0) + 1
+                        }
+                        Literal::Pos(atom) => strata.get(&atom.indicator()).copied().unwrap_or(0),
+                    });
+                }
+                let stratum = stratum.min(limit);
+                if stratum != current {
+                    strata.insert(head, stratum);
+                    changed = true;
+                }
+            }
+        }
+        strata
+    }
     /// ## 规则标准化
-    /// 为一条规则中的变量追加指定序号
+    /// 将一条规则中所有变量的编号设为指定的新编号，使其与推理中的其他变量互不相同
     pub fn rule_standardize(r: &Rule, i: usize) -> Rule {
-        let mut new_condition = Vec::<Atom>::new();
+        let mut new_condition = Vec::<Literal>::new();
         for condition in r.condition.iter() {
-            new_condition.push(KB::index_atom(condition, i));
+            new_condition.push(match condition {
+                Literal::Pos(atom) => Literal::Pos(KB::index_atom(atom, i)),
+                Literal::Neg(atom) => Literal::Neg(KB::index_atom(atom, i)),
+            });
         }
         Rule {
             condition: new_condition,
@@ -243,3 +724,93 @@ impl KB {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rule_standardize() {
+        let rule = parser::parse_kb("p(X1, X) :- q(X1).")
+            .unwrap()
+            .rules
+            .remove(0);
+        let a = KB::rule_standardize(&rule, 1).conclusion;
+        let b = KB::rule_standardize(&rule, 11).conclusion;
+        assert_ne!(a.args[0], b.args[1]);
+        assert_eq!(a.to_string(), "p(X1_1, X_1)");
+        let renamed = b.rename_apart(&mut 11);
+        assert_eq!(renamed.to_string(), "p(X1_12, X_13)");
+        let json = serde_json::to_string(&renamed.args).unwrap();
+        assert_eq!(
+            json,
+            r#"[{"Var":{"name":"X1","id":12}},{"Var":{"name":"X","id":13}}]"#
+        );
+        assert_eq!(
+            serde_json::from_str::<Vec<Symbol>>(&json).unwrap(),
+            renamed.args
+        );
+        let json = serde_json::to_string(&var("x")).unwrap();
+        assert_eq!(json, r#"{"Var":"x"}"#);
+    }
+
+    #[test]
+    fn test_empty_func() {
+        assert_eq!(func("f", vec![]), val("f"));
+        // 反序列化仍可能得到没有参数的函数符号
+        let empty: Symbol = serde_json::from_str(r#"{"Func":["f",[]]}"#).unwrap();
+        assert_eq!(empty.to_string(), "f()");
+        assert_eq!(func("f", vec![val("a"), var("x")]).to_string(), "f(a,X)");
+    }
+
+    #[test]
+    fn test_check_arity() {
+        let kb = parser::parse_kb("p(X) :- q(X, a). q(a, a). p(b).").unwrap();
+        assert!(kb.check_arity().is_ok());
+        let kb = parser::parse_kb("p(X) :- q(X, a). q(a).").unwrap();
+        assert_eq!(
+            kb.check_arity().unwrap_err().to_string(),
+            "参数个数不一致：第0条规则中为q/2，第1条规则中为q/1"
+        );
+    }
+    #[test]
+    fn test_negation() {
+        let literal = not(pred("allied", vec![var("x"), val("america")]));
+        assert_eq!(literal.to_string(), "\\+ allied(X, america)");
+        assert!(literal.is_negative());
+        assert_eq!(
+            literal.atom(),
+            &pred("allied", vec![var("x"), val("america")])
+        );
+        let json = serde_json::to_string(&literal).unwrap();
+        assert_eq!(
+            json,
+            r#"{"not":{"predicate":"allied","args":[{"Var":"x"},{"Val":"america"}]}}"#
+        );
+        assert_eq!(serde_json::from_str::<Literal>(&json).unwrap(), literal);
+        let positive = Literal::from(pred("q", vec![]));
+        let json = serde_json::to_string(&positive).unwrap();
+        assert_eq!(json, r#"{"predicate":"q","args":[]}"#);
+        assert_eq!(serde_json::from_str::<Literal>(&json).unwrap(), positive);
+        // 名为`\+`的谓词只是普通的原子公式
+        let atom = pred("\\+", vec![val("p")]);
+        assert_eq!(Literal::from(atom.clone()).atom(), &atom);
+    }
+
+    #[test]
+    fn test_check_negation() {
+        let kb = parser::parse_kb(
+            "hostile(X) :- enemy(X), \\+ allied(X). allied(X) :- treaty(X, Y), \\+ broken(Y).",
+        )
+        .unwrap();
+        assert!(kb.check_negation().is_ok());
+        let strata = kb.strata();
+        assert_eq!(strata[&pred("allied", vec![val("a")]).indicator()], 1);
+        assert_eq!(strata[&pred("hostile", vec![val("a")]).indicator()], 2);
+        let kb = parser::parse_kb("p(X) :- q(X), \\+ r(X). r(X) :- p(X).").unwrap();
+        assert_eq!(
+            kb.check_negation().unwrap_err().to_string(),
+            "知识库无法分层：第0条规则中p/1经由否定条件\\+ r(X)依赖于自身"
+        );
+    }
+}
@@ -0,0 +1,335 @@
+//! ## 魔集改写
+//! 自底向上求值会求出知识库的全部事实，而回答一个命题往往只需要其中一小部分。
+//! 魔集改写按命题中各参数是否已确定（绑定模式）改写规则：为每个由规则定义的谓词及其绑定模式
+//! 引入魔谓词，记录求值中实际需要的调用；每条规则只在其结论的调用被需要时才参与推理，
+//! 从而使自底向上求值只推出与命题相关的事实。
+//! 条件中已确定的参数按从左到右的顺序传递，即某个条件之前的条件中出现过的变量视为已确定。
+//! 否定条件需要被否定谓词的全部事实，因此被否定的谓词及其依赖的谓词不做改写，
+//! 否定条件也不确定任何变量
+
+use super::{Atom, KB, Literal, Name, PredicateIndicator, Rule, Symbol};
+use std::collections::HashSet;
+use std::iter::once;
+
+/// 绑定模式，第k项表示第k个参数是否已确定
+type Adornment = Vec<bool>;
+
+/// 参数中的变量是否都已确定
+fn is_bound(arg: &Symbol, bound: &[Symbol]) -> bool {
+    let mut vars = Vec::new();
+    arg.collect_vars(&mut vars);
+    vars.iter().all(|v| bound.contains(v))
+}
+
+/// 以`b`、`f`表示的绑定模式，如`bf`
+fn adornment_str(adornment: &Adornment) -> String {
+    adornment
+        .iter()
+        .map(|&b| if b { 'b' } else { 'f' })
+        .collect()
+}
+
+/// ## 改写生成的谓词名所用的分隔符
+/// 谓词名可以加引号书写或由JSON读入，任何字符串都可能是知识库中的谓词名。
+/// 因此取知识库与命题中的谓词名都不含有的最短的`#`串作为分隔符，
+/// 生成的谓词名都含有分隔符，不会与知识库中的谓词重名
+fn separator(kb: &KB, goal: &Atom) -> String {
+    let names: HashSet<Name> = kb
+        .rules
+        .iter()
+        .flat_map(|rule| once(&rule.conclusion).chain(rule.condition.iter().map(Literal::atom)))
+        .chain(once(goal))
+        .map(|atom| atom.predicate)
+        .collect();
+    let mut separator = String::from("#");
+    while names
+        .iter()
+        .any(|name| name.as_str().contains(separator.as_str()))
+    {
+        separator.push('#');
+    }
+    separator
+}
+
+/// 带绑定模式的谓词，如`path#bf(X, Y)`
+fn adorned(atom: &Atom, adornment: &Adornment, separator: &str) -> Atom {
+    Atom {
+        predicate: Name::new(&format!(
+            "{}{separator}{}",
+            atom.predicate,
+            adornment_str(adornment)
+        )),
+        args: atom.args.clone(),
+    }
+}
+
+/// 魔谓词，参数为原子公式中已确定的参数，如`magic#path#bf(X)`
+fn magic(atom: &Atom, adornment: &Adornment, separator: &str) -> Atom {
+    Atom {
+        predicate: Name::new(&format!(
+            "magic{separator}{}{separator}{}",
+            atom.predicate,
+            adornment_str(adornment)
+        )),
+        args: atom
+            .args
+            .iter()
+            .zip(adornment)
+            .filter(|(_, b)| **b)
+            .map(|(arg, _)| arg.clone())
+            .collect(),
+    }
+}
+
+/// ## 需要完整求值的谓词
+/// 即在否定条件中出现的谓词，以及它们的规则条件中直接或间接出现的谓词
+fn complete(kb: &KB) -> HashSet<PredicateIndicator> {
+    let mut pending: Vec<PredicateIndicator> = kb
+        .rules
+        .iter()
+        .flat_map(|rule| &rule.condition)
+        .filter(|condition| condition.is_negative())
+        .map(|condition| condition.atom().indicator())
+        .collect();
+    let mut complete = HashSet::<PredicateIndicator>::new();
+    while let Some(indicator) = pending.pop() {
+        if !complete.insert(indicator) {
+            continue;
+        }
+        for rule in kb
+            .rules
+            .iter()
+            .filter(|rule| rule.conclusion.indicator() == indicator)
+        {
+            for condition in &rule.condition {
+                pending.push(condition.atom().indicator());
+            }
+        }
+    }
+    complete
+}
+
+/// ## 魔集改写
+/// 按命题的绑定模式改写知识库，返回改写后的知识库与对应的待证命题。
+/// 改写后的命题与原命题的解相同；原知识库为Datalog程序时改写结果仍为Datalog程序，能分层时仍能分层
+pub(crate) fn magic_sets(kb: &KB, goal: &Atom) -> (KB, Atom) {
+    // 由带条件的规则定义的谓词需要改写，其余谓词只有事实，与需要完整求值的谓词一同保持不变
+    let complete = complete(kb);
+    let derived: HashSet<PredicateIndicator> = kb
+        .rules
+        .iter()
+        .filter(|rule| !rule.condition.is_empty())
+        .map(|rule| rule.conclusion.indicator())
+        .filter(|indicator| !complete.contains(indicator))
+        .collect();
+    let mut rules: Vec<Rule> = kb
+        .rules
+        .iter()
+        .filter(|rule| !derived.contains(&rule.conclusion.indicator()))
+        .cloned()
+        .collect();
+    if !derived.contains(&goal.indicator()) {
+        return (KB::new(rules), goal.clone());
+    }
+    let separator = separator(kb, goal);
+    let goal_adornment: Adornment = goal.args.iter().map(|arg| is_bound(arg, &[])).collect();
+    rules.push(Rule {
+        condition: vec![],
+        conclusion: magic(goal, &goal_adornment, &separator),
+    });
+    let mut seen = HashSet::from([(goal.indicator(), goal_adornment.clone())]);
+    let mut pending = vec![(goal.indicator(), goal_adornment.clone())];
+    while let Some((indicator, adornment)) = pending.pop() {
+        for rule in kb
+            .rules
+            .iter()
+            .filter(|rule| rule.conclusion.indicator() == indicator)
+        {
+            let head = &rule.conclusion;
+            let mut bound = Vec::<Symbol>::new();
+            for (arg, _) in head.args.iter().zip(&adornment).filter(|(_, b)| **b) {
+                arg.collect_vars(&mut bound);
+            }
+            let mut body: Vec<Literal> = vec![magic(head, &adornment, &separator).into()];
+            for condition in &rule.condition {
+                let atom = condition.atom();
+                // 被否定的谓词需要完整求值，不在改写之列
+                if derived.contains(&atom.indicator()) {
+                    let condition_adornment: Adornment =
+                        atom.args.iter().map(|arg| is_bound(arg, &bound)).collect();
+                    // 该条件的调用由结论的调用及其之前的条件决定。
+                    // 左递归的规则会得到`magic#p(X) :- magic#p(X)`，无需加入
+                    // 否定条件不确定变量，魔规则中省去也不影响改写的正确性
+                    let called_magic = magic(atom, &condition_adornment, &separator);
+                    let magic_body: Vec<Literal> = body
+                        .iter()
+                        .filter(|literal| !literal.is_negative())
+                        .cloned()
+                        .collect();
+                    if magic_body != [called_magic.clone().into()] {
+                        rules.push(Rule {
+                            condition: magic_body,
+                            conclusion: called_magic,
+                        });
+                    }
+                    let called = (atom.indicator(), condition_adornment.clone());
+                    if seen.insert(called.clone()) {
+                        pending.push(called);
+                    }
+                    body.push(adorned(atom, &condition_adornment, &separator).into());
+                } else {
+                    body.push(condition.clone());
+                }
+                if !condition.is_negative() {
+                    for arg in &atom.args {
+                        arg.collect_vars(&mut bound);
+                    }
+                }
+            }
+            rules.push(Rule {
+                condition: body,
+                conclusion: adorned(head, &adornment, &separator),
+            });
+        }
+    }
+    (KB::new(rules), adorned(goal, &goal_adornment, &separator))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bc::{SearchConfig, bc_solutions};
+    use crate::datalog::Database;
+    use crate::parser::{parse_kb, rule_to_prolog};
+    use std::ops::ControlFlow;
+
+    fn sorted(answers: Vec<Vec<crate::Theta>>) -> Vec<String> {
+        let mut answers: Vec<String> = answers
+            .iter()
+            .map(|bindings| {
+                bindings
+                    .iter()
+                    .map(|theta| theta.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            })
+            .collect();
+        answers.sort();
+        answers
+    }
+
+    #[test]
+    fn test_magic_rewrite() {
+        let kb = parse_kb(
+            "path(X, Z) :- edge(X, Y), path(Y, Z).
+             path(X, Y) :- edge(X, Y).
+             edge(a, b).",
+        )
+        .unwrap();
+        let (rewritten, goal) = magic_sets(&kb, &Atom::parse("path(a, X)").unwrap());
+        let rules: Vec<String> = rewritten.rules.iter().map(rule_to_prolog).collect();
+        assert_eq!(
+            rules,
+            [
+                "edge(a, b).",
+                "'magic#path#bf'(a).",
+                "'magic#path#bf'(Y) :- 'magic#path#bf'(X), edge(X, Y).",
+                "'path#bf'(X, Z) :- 'magic#path#bf'(X), edge(X, Y), 'path#bf'(Y, Z).",
+                "'path#bf'(X, Y) :- 'magic#path#bf'(X), edge(X, Y).",
+            ]
+        );
+        assert_eq!(goal.to_string(), "path#bf(a, X)");
+    }
+
+    #[test]
+    fn test_magic_only_relevant_facts() {
+        let kb = parse_kb(
+            "path(X, Z) :- path(X, Y), edge(Y, Z).
+             path(X, Y) :- edge(X, Y).
+             edge(a, b). edge(b, c). edge(x, y). edge(y, z). edge(z, x).",
+        )
+        .unwrap();
+        let goal = Atom::parse("path(a, X)").unwrap();
+        let (rewritten, magic_goal) = magic_sets(&kb, &goal);
+        let full = Database::new(&kb).unwrap();
+        let relevant = Database::new(&rewritten).unwrap();
+        assert_eq!(
+            sorted(relevant.query(&magic_goal)),
+            sorted(full.query(&goal))
+        );
+        // 5条边、1条魔事实与从a出发的2条路径；完整求值还会推出b到c与x、y、z之间的10条路径
+        assert_eq!(relevant.len(), 8);
+        assert_eq!(full.len(), 17);
+    }
+
+    #[test]
+    fn test_magic_negation() {
+        let kb = parse_kb(
+            "safe(X, Y) :- path(X, Y), \\+ risky(Y).
+             risky(Y) :- path(Y, Z), trap(Z).
+             path(X, Z) :- edge(X, Y), path(Y, Z).
+             path(X, Y) :- edge(X, Y).
+             edge(a, b). edge(b, c). edge(c, d). edge(x, y). trap(d).",
+        )
+        .unwrap();
+        let goal = Atom::parse("safe(a, X)").unwrap();
+        let (rewritten, magic_goal) = magic_sets(&kb, &goal);
+        // 被否定的risky及其依赖的path保持不变，safe仍按绑定模式改写
+        assert!(rewritten.rules.contains(&kb.rules[1]));
+        assert!(rewritten.rules.contains(&kb.rules[2]));
+        assert_eq!(magic_goal.to_string(), "safe#bf(a, X)");
+        let full = Database::new(&kb).unwrap();
+        let relevant = Database::new(&rewritten).unwrap();
+        assert_eq!(
+            sorted(relevant.query(&magic_goal)),
+            sorted(full.query(&goal))
+        );
+        assert_eq!(sorted(full.query(&goal)), ["X = d"]);
+    }
+
+    #[test]
+    fn test_magic_name_clash() {
+        // 知识库中已有与生成的谓词同名的谓词时，改用更长的分隔符
+        let kb = parse_kb("'p#f'(z). p(X) :- q(X). q(a).").unwrap();
+        let goal = Atom::parse("p(X)").unwrap();
+        let (rewritten, magic_goal) = magic_sets(&kb, &goal);
+        assert_eq!(magic_goal.to_string(), "p##f(X)");
+        assert!(rewritten.rules.contains(&kb.rules[0]));
+        let database = Database::new(&rewritten).unwrap();
+        assert_eq!(sorted(database.query(&magic_goal)), ["X = a"]);
+    }
+
+    #[test]
+    fn test_magic_agrees_with_bc() {
+        let kb = KB::load("examples/AIMA/knowledge_base.json").unwrap();
+        let config = SearchConfig {
+            max_depth: 10,
+            ..SearchConfig::default()
+        };
+        for goal in [
+            "criminal(X)",
+            "criminal(west)",
+            "criminal(nono)",
+            "sells(west, X, Y)",
+            "sells(X, m1, nono)",
+            "weapon(X)",
+            "hostile(X)",
+            "american(X)",
+        ] {
+            let goal = Atom::parse(goal).unwrap();
+            let mut expected = Vec::new();
+            let summary = bc_solutions(&kb, &goal, &config, |bindings| {
+                expected.push(bindings);
+                ControlFlow::Continue(())
+            });
+            assert_eq!(summary.limit, None);
+            let (rewritten, magic_goal) = magic_sets(&kb, &goal);
+            let database = Database::new(&rewritten).unwrap();
+            assert_eq!(
+                sorted(database.query(&magic_goal)),
+                sorted(expected),
+                "{goal}"
+            );
+        }
+    }
+}
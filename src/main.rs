@@ -1,24 +1,60 @@
 use clap::Parser;
-use reasoning::{
-    ReasoningError,
-    cli::{Cli, prove},
-};
+use reasoning::cli::{Cli, Command, EXIT_ERROR, convert, prove, repl, saturate};
+use reasoning::{Outcome, ReasoningError};
+use std::process::ExitCode;
 
-fn main() -> Result<(), ReasoningError> {
+fn main() -> ExitCode {
     let args = Cli::parse();
-    let provement = prove(&args);
-    if let Err(e) = provement {
-        match e {
-            ReasoningError::ProofNotFound => {
-                println!("无法证明命题为真");
-            }
-            _ => {
+    if let Some(command) = &args.command {
+        let result = match command {
+            Command::Convert(convert_args) => convert(convert_args).map(|()| ExitCode::SUCCESS),
+            Command::Repl(repl_args) => repl(repl_args).map(|()| ExitCode::SUCCESS),
+            Command::Saturate(saturate_args) => saturate(saturate_args),
+        };
+        return match result {
+            Ok(code) => code,
+            Err(e) => {
                 eprintln!("{e}");
-                return Err(e);
+                ExitCode::from(EXIT_ERROR)
             }
+        };
+    }
+    let report = match prove(&args) {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("{e}");
+            return ExitCode::from(EXIT_ERROR);
+        }
+    };
+    if report.answers.is_empty() {
+        match (report.outcome, report.limit) {
+            (Outcome::Floundered, _) => eprintln!("{}", ReasoningError::Floundering),
+            (_, Some(limit)) => println!("无法证明命题为真：{limit}"),
+            (_, None) => println!("无法证明命题为真"),
+        }
+        return report.exit_code();
+    }
+    println!("命题为真");
+    for answer in &report.answers {
+        let bindings: Vec<String> = answer
+            .bindings
+            .iter()
+            .map(|theta| theta.to_string())
+            .collect();
+        if !bindings.is_empty() {
+            println!("{}", bindings.join(", "));
+        }
+        if let Some(proof) = &answer.proof {
+            println!("{}", proof.trim_end());
+        }
+    }
+    if args.all || args.limit.is_some() {
+        if let Some(limit) = report.limit {
+            eprintln!("{limit}，可能还有未找到的解");
+        }
+        if report.floundered {
+            eprintln!("{}，可能还有未找到的解", ReasoningError::Floundering);
         }
-    } else {
-        println!("命题为真");
     }
-    Ok(())
+    report.exit_code()
 }
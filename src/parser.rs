@@ -0,0 +1,543 @@
+//! ## 类Prolog语法的知识库解析器
+//! 支持形如`leq(X, Z) :- leq(X, Y), leq(Y, Z).`的子句与`leq(seven, add(three, nine))`的命题。
+//! 大写字母或下划线开头的标识符为变量（单独的`_`为匿名变量），其余标识符与数字为常量，
+//! 单引号括起的内容为常量名，`%`之后直到行末为注释。
+//! 规则的条件可以是否定`\+ p(X)`。
+//! 指令`:- table leq/2, path/2.`声明使用表格化求解的谓词
+
+use super::{
+    ANONYMOUS_PREFIX, Atom, KB, Literal, PredicateIndicator, ReasoningError, Rule, Symbol, func,
+    not, pred, val, var,
+};
+use crate::intern::Name;
+
+/// 词法单元
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// 常量、函数符号或谓词名
+    Name(String),
+    /// 变量名
+    Var(String),
+    LParen,
+    RParen,
+    Comma,
+    /// 子句结尾的`.`
+    Dot,
+    /// `:-`
+    Neck,
+    /// 谓词标识中的`/`
+    Slash,
+    /// 否定`\+`
+    Not,
+    Eof,
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Name(name) => format!("`{name}`"),
+            Token::Var(name) => format!("变量`{name}`"),
+            Token::LParen => "`(`".to_string(),
+            Token::RParen => "`)`".to_string(),
+            Token::Comma => "`,`".to_string(),
+            Token::Dot => "`.`".to_string(),
+            Token::Neck => "`:-`".to_string(),
+            Token::Slash => "`/`".to_string(),
+            Token::Not => "`\\+`".to_string(),
+            Token::Eof => "输入结尾".to_string(),
+        }
+    }
+}
+
+/// 带有位置（行、列，均从1开始）的词法单元
+struct Spanned {
+    token: Token,
+    line: usize,
+    column: usize,
+}
+
+fn syntax_error(line: usize, column: usize, message: impl Into<String>) -> ReasoningError {
+    ReasoningError::SyntaxError(format!("第{line}行第{column}列：{}", message.into()))
+}
+
+/// 词法分析
+fn tokenize(src: &str) -> Result<Vec<Spanned>, ReasoningError> {
+    let mut tokens = Vec::<Spanned>::new();
+    let mut chars = src.chars().peekable();
+    let (mut line, mut column) = (1, 1);
+    while let Some(&c) = chars.peek() {
+        let (start_line, start_column) = (line, column);
+        let mut bump = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+            let c = chars.next();
+            if c == Some('\n') {
+                line += 1;
+                column = 1;
+            } else {
+                column += 1;
+            }
+            c
+        };
+        let token = match c {
+            c if c.is_whitespace() => {
+                bump(&mut chars);
+                continue;
+            }
+            '%' => {
+                while chars.peek().is_some_and(|&c| c != '\n') {
+                    bump(&mut chars);
+                }
+                continue;
+            }
+            '(' => {
+                bump(&mut chars);
+                Token::LParen
+            }
+            ')' => {
+                bump(&mut chars);
+                Token::RParen
+            }
+            ',' => {
+                bump(&mut chars);
+                Token::Comma
+            }
+            '.' => {
+                bump(&mut chars);
+                Token::Dot
+            }
+            '/' => {
+                bump(&mut chars);
+                Token::Slash
+            }
+            '\\' => {
+                bump(&mut chars);
+                if bump(&mut chars) != Some('+') {
+                    return Err(syntax_error(start_line, start_column, "应为`\\+`"));
+                }
+                Token::Not
+            }
+            ':' => {
+                bump(&mut chars);
+                if bump(&mut chars) != Some('-') {
+                    return Err(syntax_error(start_line, start_column, "应为`:-`"));
+                }
+                Token::Neck
+            }
+            '\'' => {
+                bump(&mut chars);
+                let mut name = String::new();
+                loop {
+                    match bump(&mut chars) {
+                        Some('\'') => break,
+                        Some(c) => name.push(c),
+                        None => {
+                            return Err(syntax_error(start_line, start_column, "单引号未闭合"));
+                        }
+                    }
+                }
+                Token::Name(name)
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut name = String::new();
+                while let Some(&c) = chars.peek() {
+                    if !(c.is_alphanumeric() || c == '_') {
+                        break;
+                    }
+                    name.push(c);
+                    bump(&mut chars);
+                }
+                if c.is_uppercase() || c == '_' {
+                    Token::Var(name)
+                } else {
+                    Token::Name(name)
+                }
+            }
+            c => {
+                return Err(syntax_error(
+                    start_line,
+                    start_column,
+                    format!("无法识别的字符`{c}`"),
+                ));
+            }
+        };
+        tokens.push(Spanned {
+            token,
+            line: start_line,
+            column: start_column,
+        });
+    }
+    tokens.push(Spanned {
+        token: Token::Eof,
+        line,
+        column,
+    });
+    Ok(tokens)
+}
+
+/// 递归下降语法分析器
+struct Parser {
+    tokens: Vec<Spanned>,
+    pos: usize,
+    /// 已出现的匿名变量个数，用于为每个`_`生成互不相同的变量
+    anonymous: usize,
+}
+
+impl Parser {
+    fn new(src: &str) -> Result<Self, ReasoningError> {
+        Ok(Parser {
+            tokens: tokenize(src)?,
+            pos: 0,
+            anonymous: 0,
+        })
+    }
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos].token
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.tokens[self.pos].token.clone();
+        if token != Token::Eof {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn error(&self, message: impl Into<String>) -> ReasoningError {
+        let spanned = &self.tokens[self.pos];
+        syntax_error(spanned.line, spanned.column, message)
+    }
+
+    fn unexpected(&self, expected: &str) -> ReasoningError {
+        self.error(format!("应为{expected}，实为{}", self.peek().describe()))
+    }
+
+    fn expect(&mut self, token: Token) -> Result<(), ReasoningError> {
+        if *self.peek() == token {
+            self.next();
+            Ok(())
+        } else {
+            Err(self.unexpected(&token.describe()))
+        }
+    }
+
+    /// 括号内以逗号分隔的项
+    fn args(&mut self) -> Result<Vec<Symbol>, ReasoningError> {
+        let mut args = Vec::<Symbol>::new();
+        if *self.peek() != Token::LParen {
+            return Ok(args);
+        }
+        self.next();
+        loop {
+            args.push(self.term()?);
+            match self.peek() {
+                Token::Comma => {}
+                Token::RParen => {
+                    self.next();
+                    return Ok(args);
+                }
+                _ => return Err(self.unexpected("`,`或`)`")),
+            }
+            self.next();
+        }
+    }
+
+    fn term(&mut self) -> Result<Symbol, ReasoningError> {
+        match self.peek().clone() {
+            Token::Var(name) => {
+                self.next();
+                if name == "_" {
+                    self.anonymous += 1;
+                    Ok(var(format!("{ANONYMOUS_PREFIX}{}", self.anonymous)))
+                } else {
+                    Ok(var(name))
+                }
+            }
+            Token::Name(name) => {
+                self.next();
+                let args = self.args()?;
+                if args.is_empty() {
+                    Ok(val(name))
+                } else {
+                    Ok(func(name, args))
+                }
+            }
+            _ => Err(self.unexpected("项")),
+        }
+    }
+
+    fn atom(&mut self) -> Result<Atom, ReasoningError> {
+        match self.peek().clone() {
+            Token::Name(name) => {
+                self.next();
+                Ok(pred(name, self.args()?))
+            }
+            _ => Err(self.unexpected("谓词")),
+        }
+    }
+
+    /// 条件：原子公式或其否定`\+ atom`
+    fn literal(&mut self) -> Result<Literal, ReasoningError> {
+        if *self.peek() == Token::Not {
+            self.next();
+            Ok(not(self.atom()?))
+        } else {
+            Ok(self.atom()?.into())
+        }
+    }
+
+    /// 谓词标识：`name/arity`
+    fn indicator(&mut self) -> Result<PredicateIndicator, ReasoningError> {
+        let Token::Name(predicate) = self.peek().clone() else {
+            return Err(self.unexpected("谓词"));
+        };
+        self.next();
+        self.expect(Token::Slash)?;
+        match self.peek().clone() {
+            Token::Name(arity) if arity.parse::<usize>().is_ok() => {
+                self.next();
+                Ok(PredicateIndicator {
+                    predicate: Name::new(&predicate),
+                    arity: arity.parse().unwrap(),
+                })
+            }
+            _ => Err(self.unexpected("参数个数")),
+        }
+    }
+
+    /// 指令：`:- table name/arity, ....`
+    fn directive(&mut self) -> Result<Vec<PredicateIndicator>, ReasoningError> {
+        self.expect(Token::Neck)?;
+        match self.peek().clone() {
+            Token::Name(name) if name == "table" => {
+                self.next();
+            }
+            Token::Name(name) => return Err(self.error(format!("不支持指令`{name}`"))),
+            _ => return Err(self.unexpected("指令")),
+        }
+        let mut indicators = vec![self.indicator()?];
+        while *self.peek() == Token::Comma {
+            self.next();
+            indicators.push(self.indicator()?);
+        }
+        self.expect(Token::Dot)?;
+        Ok(indicators)
+    }
+
+    /// 子句：`head.`或`head :- body1, body2.`
+    fn clause(&mut self) -> Result<Rule, ReasoningError> {
+        let conclusion = self.atom()?;
+        let mut condition = Vec::<Literal>::new();
+        if *self.peek() == Token::Neck {
+            self.next();
+            condition.push(self.literal()?);
+            while *self.peek() == Token::Comma {
+                self.next();
+                condition.push(self.literal()?);
+            }
+        }
+        self.expect(Token::Dot)?;
+        Ok(Rule {
+            condition,
+            conclusion,
+        })
+    }
+}
+
+/// ## 解析类Prolog语法的知识库
+pub(crate) fn parse_kb(src: &str) -> Result<KB, ReasoningError> {
+    let mut parser = Parser::new(src)?;
+    let mut rules = Vec::<Rule>::new();
+    let mut tabled = Vec::<PredicateIndicator>::new();
+    while *parser.peek() != Token::Eof {
+        if *parser.peek() == Token::Neck {
+            tabled.extend(parser.directive()?);
+        } else {
+            rules.push(parser.clause()?);
+        }
+    }
+    Ok(KB { rules, tabled })
+}
+
+/// ## 解析类Prolog语法的待证命题
+/// 命题末尾的`.`可以省略
+pub(crate) fn parse_atom(src: &str) -> Result<Atom, ReasoningError> {
+    let mut parser = Parser::new(src)?;
+    let atom = parser.atom()?;
+    if *parser.peek() == Token::Dot {
+        parser.next();
+    }
+    if *parser.peek() != Token::Eof {
+        return Err(parser.unexpected("输入结尾"));
+    }
+    Ok(atom)
+}
+
+/// 以类Prolog语法输出项，必要时为常量名加引号
+fn symbol_to_prolog(x: &Symbol) -> String {
+    match x {
+        Symbol::Var(_) if x.is_anonymous() => "_".to_string(),
+        Symbol::Var(_) => x.to_string(),
+        Symbol::Val(name) => name_to_prolog(name.as_str()),
+        Symbol::Func(name, args) => format!(
+            "{}({})",
+            name_to_prolog(name.as_str()),
+            args_to_prolog(args)
+        ),
+    }
+}
+
+fn args_to_prolog(args: &[Symbol]) -> String {
+    args.iter()
+        .map(symbol_to_prolog)
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// 以小写字母或数字开头且只含字母、数字与下划线的名称可以直接书写，否则需加引号
+fn name_to_prolog(name: &str) -> String {
+    let plain = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_lowercase() || c.is_ascii_digit())
+        && name.chars().all(|c| c.is_alphanumeric() || c == '_');
+    if plain {
+        name.to_string()
+    } else {
+        format!("'{name}'")
+    }
+}
+
+/// ## 以类Prolog语法输出原子公式
+pub(crate) fn atom_to_prolog(atom: &Atom) -> String {
+    if atom.args.is_empty() {
+        name_to_prolog(atom.predicate.as_str())
+    } else {
+        format!(
+            "{}({})",
+            name_to_prolog(atom.predicate.as_str()),
+            args_to_prolog(&atom.args)
+        )
+    }
+}
+
+/// 以类Prolog语法输出规则的条件
+fn literal_to_prolog(literal: &Literal) -> String {
+    match literal {
+        Literal::Pos(atom) => atom_to_prolog(atom),
+        Literal::Neg(atom) => format!("\\+ {}", atom_to_prolog(atom)),
+    }
+}
+
+/// ## 以类Prolog语法输出规则
+/// 输出结果可由`parse_kb`重新读入
+pub(crate) fn rule_to_prolog(rule: &Rule) -> String {
+    let conclusion = atom_to_prolog(&rule.conclusion);
+    if rule.condition.is_empty() {
+        format!("{conclusion}.")
+    } else {
+        let condition: Vec<String> = rule.condition.iter().map(literal_to_prolog).collect();
+        format!("{conclusion} :- {}.", condition.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_kb() {
+        let kb = parse_kb(
+            "% 传递性\nleq(X, Z) :- leq(X, Y), leq(Y, Z).\nleq(X, add(X, zero)).\nleq(seven, nine).\n",
+        )
+        .unwrap();
+        assert_eq!(kb.rules.len(), 3);
+        assert_eq!(
+            kb.rules[0].to_string(),
+            "leq(X, Y) ^ leq(Y, Z) => leq(X, Z)"
+        );
+        assert_eq!(
+            kb.rules[1].conclusion,
+            pred(
+                "leq",
+                vec![var("X"), func("add", vec![var("X"), val("zero")])]
+            )
+        );
+        assert!(kb.rules[2].is_fact());
+    }
+
+    #[test]
+    fn test_parse_atom() {
+        assert_eq!(
+            parse_atom("leq(seven, add(three, nine))").unwrap(),
+            pred(
+                "leq",
+                vec![val("seven"), func("add", vec![val("three"), val("nine")])]
+            )
+        );
+        let atom = parse_atom("p(_, _, 'New York', _G1).").unwrap();
+        assert_ne!(atom.args[0], atom.args[1]);
+        assert!(atom.args[0].is_anonymous());
+        assert_eq!(atom.args[2], val("New York"));
+        assert!(!atom.args[3].is_anonymous());
+        assert!(!atom.args[..2].contains(&atom.args[3]));
+    }
+
+    #[test]
+    fn test_rule_to_prolog() {
+        let src = "leq(add(W, X), add(Y, Z)) :- leq(W, Y), leq(X, Z).";
+        let kb = parse_kb(src).unwrap();
+        assert_eq!(rule_to_prolog(&kb.rules[0]), src);
+        let src = "p(X) :- q(X, _), r(_).";
+        assert_eq!(rule_to_prolog(&parse_kb(src).unwrap().rules[0]), src);
+        let rule = Rule {
+            condition: vec![],
+            conclusion: pred("city", vec![val("New York")]),
+        };
+        assert_eq!(rule_to_prolog(&rule), "city('New York').");
+        assert_eq!(parse_kb(&rule_to_prolog(&rule)).unwrap().rules[0], rule);
+    }
+
+    #[test]
+    fn test_parse_negation() {
+        let src = "hostile(X) :- enemy(X, america), \\+ allied(X, america).";
+        let kb = parse_kb(src).unwrap();
+        assert_eq!(
+            kb.rules[0].condition[1],
+            not(pred("allied", vec![var("X"), val("america")]))
+        );
+        assert_eq!(rule_to_prolog(&kb.rules[0]), src);
+        let err = parse_atom("\\+ allied(nono, america)").unwrap_err();
+        assert_eq!(err.to_string(), "语法错误：第1行第1列：应为谓词，实为`\\+`");
+        // 引号括起的`\+`是普通的谓词名
+        let rule = parse_kb("p :- '\\+'(q).").unwrap().rules.remove(0);
+        assert_eq!(rule.condition, vec![pred("\\+", vec![val("q")]).into()]);
+        assert_eq!(rule_to_prolog(&rule), "p :- '\\+'(q).");
+        let err = parse_kb("\\+ p(a).").unwrap_err();
+        assert_eq!(err.to_string(), "语法错误：第1行第1列：应为谓词，实为`\\+`");
+        let err = parse_kb("p :- \\q.").unwrap_err();
+        assert_eq!(err.to_string(), "语法错误：第1行第6列：应为`\\+`");
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let err = parse_kb("leq(X, Y) :- leq(X, Z)\nleq(a, b).").unwrap_err();
+        assert_eq!(err.to_string(), "语法错误：第2行第1列：应为`.`，实为`leq`");
+        let err = parse_atom("leq(a, b").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "语法错误：第1行第9列：应为`,`或`)`，实为输入结尾"
+        );
+        let err = parse_kb(":- dynamic p/1.").unwrap_err();
+        assert_eq!(err.to_string(), "语法错误：第1行第4列：不支持指令`dynamic`");
+    }
+
+    #[test]
+    fn test_parse_table_directive() {
+        let kb = parse_kb(":- table leq/2, path/2.\nleq(X, X).").unwrap();
+        let tabled: Vec<String> = kb.tabled.iter().map(|p| p.to_string()).collect();
+        assert_eq!(tabled, vec!["leq/2", "path/2"]);
+        assert!(kb.is_tabled(&pred("leq", vec![val("a"), val("a")])));
+        assert!(!kb.is_tabled(&pred("leq", vec![val("a")])));
+        assert!(parse_kb(":- table leq/two.").is_err());
+    }
+}
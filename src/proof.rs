@@ -0,0 +1,228 @@
+//! ## 证明树
+
+use super::{Atom, KB, Literal, Theta};
+use crate::bindings::Bindings;
+use crate::unify::{exhaust_subst, exhaust_subst_symbol};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+/// ## 证明树
+/// 每个节点记录一条得证的命题、证明它所用的规则、
+/// 命题与规则结论合一时施加的置换，以及规则每个前提的证明
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProofTree {
+    /// 得证的命题，否定时为被否定的命题
+    pub(crate) goal: Atom,
+    /// 是否为以有限失败得证的否定
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) negated: bool,
+    /// 所用规则在知识库中的序号，以有限失败得证的否定没有所用的规则，为`None`
+    pub(crate) rule: Option<usize>,
+    /// 命题与规则结论合一时施加的置换
+    pub(crate) thetas: Vec<Theta>,
+    /// 规则各前提的证明，与规则的condition一一对应
+    pub(crate) children: Vec<ProofTree>,
+}
+
+impl ProofTree {
+    /// ## 由知识库中的事实直接得证的叶子节点
+    pub(crate) fn leaf(goal: Atom, rule: usize) -> Self {
+        ProofTree {
+            goal,
+            negated: false,
+            rule: Some(rule),
+            thetas: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+
+    /// ## 以有限失败得证的否定
+    /// 被否定的命题无法得证，节点没有所用的规则与子证明
+    pub(crate) fn negation(goal: Atom) -> Self {
+        ProofTree {
+            goal,
+            negated: true,
+            rule: None,
+            thetas: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+
+    /// 得证的命题，否定时为被否定的命题
+    pub fn goal(&self) -> &Atom {
+        &self.goal
+    }
+
+    /// 得证的文字，否定以`\+ goal`表示
+    pub fn literal(&self) -> Literal {
+        if self.negated {
+            Literal::Neg(self.goal.clone())
+        } else {
+            Literal::Pos(self.goal.clone())
+        }
+    }
+
+    /// 所用规则在知识库中的序号，否定没有所用的规则
+    pub fn rule(&self) -> Option<usize> {
+        self.rule
+    }
+
+    /// 命题与规则结论合一时施加的置换
+    pub fn thetas(&self) -> &[Theta] {
+        &self.thetas
+    }
+
+    /// 规则各前提的证明
+    pub fn children(&self) -> &[ProofTree] {
+        &self.children
+    }
+
+    /// ## 以绑定表实例化整棵证明树
+    /// 搜索过程中记录的命题可能含有之后才被绑定的变量，得到完整证明后再统一代入
+    pub(crate) fn resolve(&self, bindings: &Bindings) -> Self {
+        ProofTree {
+            goal: exhaust_subst(&self.goal, bindings),
+            negated: self.negated,
+            rule: self.rule,
+            thetas: self
+                .thetas
+                .iter()
+                .map(|theta| Theta {
+                    origin: theta.origin.clone(),
+                    result: exhaust_subst_symbol(&theta.result, bindings),
+                })
+                .collect(),
+            children: self
+                .children
+                .iter()
+                .map(|child| child.resolve(bindings))
+                .collect(),
+        }
+    }
+
+    /// ## 导出为Graphviz DOT格式
+    /// 节点为得证的命题，由命题指向其各前提的边标注所用的规则
+    pub fn to_dot(&self, kb: &KB) -> String {
+        let mut out = String::from("digraph proof {\n    node [shape=box];\n");
+        let mut next_id = 0;
+        self.write_dot(kb, &mut out, &mut next_id);
+        out.push_str("}\n");
+        out
+    }
+
+    fn write_dot(&self, kb: &KB, out: &mut String, next_id: &mut usize) -> usize {
+        let id = *next_id;
+        *next_id += 1;
+        let _ = writeln!(
+            out,
+            "    n{id} [label=\"{}\"];",
+            dot_escape(&self.literal().to_string())
+        );
+        for child in self.children.iter() {
+            let child_id = child.write_dot(kb, out, next_id);
+            let _ = writeln!(
+                out,
+                "    n{id} -> n{child_id} [label=\"{}\"];",
+                dot_escape(&rule_label(kb, self.rule))
+            );
+        }
+        id
+    }
+
+    /// ## 导出为Mermaid流程图
+    /// 节点为得证的命题，由命题指向其各前提的边标注所用的规则
+    pub fn to_mermaid(&self, kb: &KB) -> String {
+        let mut out = String::from("graph TD\n");
+        let mut next_id = 0;
+        self.write_mermaid(kb, &mut out, &mut next_id);
+        out
+    }
+
+    fn write_mermaid(&self, kb: &KB, out: &mut String, next_id: &mut usize) -> usize {
+        let id = *next_id;
+        *next_id += 1;
+        let _ = writeln!(
+            out,
+            "    n{id}[\"{}\"]",
+            mermaid_escape(&self.literal().to_string())
+        );
+        for child in self.children.iter() {
+            let child_id = child.write_mermaid(kb, out, next_id);
+            let _ = writeln!(
+                out,
+                "    n{id} -->|\"{}\"| n{child_id}",
+                mermaid_escape(&rule_label(kb, self.rule))
+            );
+        }
+        id
+    }
+
+    /// ## 导出为JSON
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("证明树总能序列化为JSON")
+    }
+}
+
+/// 规则的标注文字，形如`#2 missile(X) => weapon(X)`。没有所用规则的节点也没有子证明，不会用到标注
+fn rule_label(kb: &KB, rule: Option<usize>) -> String {
+    let Some(rule) = rule else {
+        return String::new();
+    };
+    match kb.rules.get(rule) {
+        Some(r) => format!("#{rule} {r}"),
+        None => format!("#{rule}"),
+    }
+}
+
+fn dot_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn mermaid_escape(s: &str) -> String {
+    s.replace('"', "#quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Rule, pred, val, var};
+
+    #[test]
+    fn test_export_proof() {
+        let kb = KB {
+            rules: vec![
+                Rule {
+                    condition: vec![pred("missile", vec![var("x")]).into()],
+                    conclusion: pred("weapon", vec![var("x")]),
+                },
+                Rule {
+                    condition: vec![],
+                    conclusion: pred("missile", vec![val("m1")]),
+                },
+            ],
+            ..Default::default()
+        };
+        let proof = ProofTree {
+            goal: pred("weapon", vec![val("m1")]),
+            negated: false,
+            rule: Some(0),
+            thetas: vec![],
+            children: vec![ProofTree::leaf(pred("missile", vec![val("m1")]), 1)],
+        };
+        assert_eq!(
+            proof.to_dot(&kb),
+            "digraph proof {\n    node [shape=box];\n    n0 [label=\"weapon(m1)\"];\n    n1 [label=\"missile(m1)\"];\n    n0 -> n1 [label=\"#0 missile(X) => weapon(X)\"];\n}\n"
+        );
+        assert_eq!(
+            proof.to_mermaid(&kb),
+            "graph TD\n    n0[\"weapon(m1)\"]\n    n1[\"missile(m1)\"]\n    n0 -->|\"#0 missile(X) => weapon(X)\"| n1\n"
+        );
+        let parsed: ProofTree = serde_json::from_str(&proof.to_json()).unwrap();
+        assert_eq!(parsed.children[0].rule, Some(1));
+        let negation = ProofTree::negation(pred("missile", vec![val("m2")]));
+        assert!(negation.to_json().contains(r#""rule": null"#));
+        let parsed: ProofTree = serde_json::from_str(&negation.to_json()).unwrap();
+        assert_eq!(parsed.rule(), None);
+        assert_eq!(parsed.literal().to_string(), "\\+ missile(m2)");
+    }
+}
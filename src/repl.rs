@@ -0,0 +1,284 @@
+//! ## 交互式推理环境
+//! 载入一次知识库后反复接受查询，并允许在会话中增删规则、调整推理参数
+
+use super::{KB, ReasoningError, Rule};
+use crate::bc::{SearchConfig, Solutions};
+use crate::cli::load_theorem;
+use crate::parser::{parse_kb, rule_to_prolog};
+use std::io::{BufRead, Write};
+use std::ops::ControlFlow;
+use std::time::Duration;
+
+const HELP: &str = "\
+<命题>            证明命题，如 leq(seven, add(three, nine))；得到一个解后输入`;`继续寻找下一个解
+assert <子句>     向知识库添加规则，如 assert leq(X, X).；也可添加指令，如 assert :- table leq/2.
+retract <子句>    从知识库删除与之相同的规则
+retract <序号>    按:listing中的序号删除规则
+:listing          列出知识库中的全部规则
+:trace on|off     开启或关闭详细证明过程
+:depth <N>        设置最大推理深度
+:steps <N>|off    设置或取消最大推理步数
+:timeout <秒>|off 设置或取消推理时间上限
+:help             显示本帮助
+:quit             退出";
+
+/// ## 交互式推理环境
+/// 从`input`逐行读取命令，向`output`输出结果
+pub struct Repl<R, W> {
+    kb: KB,
+    config: SearchConfig,
+    input: R,
+    output: W,
+}
+
+impl<R: BufRead, W: Write> Repl<R, W> {
+    pub(crate) fn new(kb: KB, input: R, output: W) -> Self {
+        Repl {
+            kb,
+            config: SearchConfig::default(),
+            input,
+            output,
+        }
+    }
+
+    /// 读取一行输入，输入结束时返回None
+    fn read_line(&mut self) -> Result<Option<String>, ReasoningError> {
+        let mut line = String::new();
+        if self.input.read_line(&mut line)? == 0 {
+            Ok(None)
+        } else {
+            Ok(Some(line))
+        }
+    }
+
+    /// ## 运行交互式推理环境直至输入结束或收到`:quit`
+    /// 单条命令出错时输出错误信息并继续运行
+    pub fn run(&mut self) -> Result<(), ReasoningError> {
+        loop {
+            write!(self.output, "?- ")?;
+            self.output.flush()?;
+            let Some(line) = self.read_line()? else {
+                writeln!(self.output)?;
+                return Ok(());
+            };
+            match self.execute(line.trim()) {
+                Ok(ControlFlow::Break(())) => return Ok(()),
+                Ok(ControlFlow::Continue(())) => {}
+                Err(e @ ReasoningError::IoError(_)) => return Err(e),
+                Err(e) => writeln!(self.output, "{e}")?,
+            }
+        }
+    }
+
+    /// 执行一条命令，返回`Break`表示退出
+    fn execute(&mut self, line: &str) -> Result<ControlFlow<()>, ReasoningError> {
+        let (command, argument) = line
+            .split_once(char::is_whitespace)
+            .map_or((line, ""), |(command, argument)| (command, argument.trim()));
+        match command {
+            "" => {}
+            ":quit" | ":q" | ":halt" => return Ok(ControlFlow::Break(())),
+            ":help" => writeln!(self.output, "{HELP}")?,
+            ":listing" => {
+                for indicator in &self.kb.tabled {
+                    writeln!(self.output, ":- table {indicator}.")?;
+                }
+                for (i, rule) in self.kb.rules.iter().enumerate() {
+                    writeln!(self.output, "#{i} {}", rule_to_prolog(rule))?;
+                }
+            }
+            ":trace" => match argument {
+                "on" => self.config.verbose = true,
+                "off" => self.config.verbose = false,
+                _ => writeln!(self.output, "用法：:trace on|off")?,
+            },
+            ":depth" => match argument.parse::<usize>() {
+                Ok(depth) => self.config.max_depth = depth,
+                Err(_) => writeln!(
+                    self.output,
+                    "用法：:depth <N>，当前为{}",
+                    self.config.max_depth
+                )?,
+            },
+            ":steps" => match argument {
+                "off" => self.config.max_steps = None,
+                _ => match argument.parse::<usize>() {
+                    Ok(steps) => self.config.max_steps = Some(steps),
+                    Err(_) => writeln!(self.output, "用法：:steps <N>|off")?,
+                },
+            },
+            ":timeout" => match argument {
+                "off" => self.config.timeout = None,
+                _ => match argument.parse::<f64>().map(Duration::try_from_secs_f64) {
+                    Ok(Ok(timeout)) => self.config.timeout = Some(timeout),
+                    _ => writeln!(self.output, "用法：:timeout <秒>|off")?,
+                },
+            },
+            "assert" => {
+                let kb = parse_kb(argument)?;
+                let added = kb.rules.len();
+                self.kb.rules.extend(kb.rules);
+                if let Err(e) = self.kb.check() {
+                    self.kb.rules.truncate(self.kb.rules.len() - added);
+                    return Err(e);
+                }
+                self.kb.tabled.extend(kb.tabled);
+                writeln!(self.output, "已添加{added}条规则")?;
+            }
+            "retract" => self.retract(argument)?,
+            _ if command.starts_with(':') => {
+                writeln!(self.output, "未知命令{command}，输入:help查看帮助")?;
+            }
+            _ => self.query(line)?,
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+
+    /// 按序号或按内容删除规则
+    fn retract(&mut self, argument: &str) -> Result<(), ReasoningError> {
+        if let Ok(i) = argument.parse::<usize>() {
+            if i < self.kb.rules.len() {
+                let rule = self.kb.rules.remove(i);
+                writeln!(self.output, "已删除规则 {}", rule_to_prolog(&rule))?;
+            } else {
+                writeln!(self.output, "不存在序号为{i}的规则")?;
+            }
+            return Ok(());
+        }
+        for rule in parse_kb(argument)?.rules {
+            match self.kb.rules.iter().position(|r: &Rule| *r == rule) {
+                Some(i) => {
+                    self.kb.rules.remove(i);
+                    writeln!(self.output, "已删除规则 {}", rule_to_prolog(&rule))?;
+                }
+                None => writeln!(self.output, "知识库中没有规则 {}", rule_to_prolog(&rule))?,
+            }
+        }
+        Ok(())
+    }
+
+    /// 证明命题并逐个输出解，每个解之后读取一行输入，为`;`时继续寻找下一个解
+    fn query(&mut self, line: &str) -> Result<(), ReasoningError> {
+        let theorem = load_theorem(line.strip_prefix("?-").unwrap_or(line).trim(), false)?;
+        let mut solutions = Solutions::traced(&self.kb, &theorem, &self.config);
+        let mut exhausted = true;
+        loop {
+            let solution = solutions.next();
+            for line in solutions.take_trace() {
+                writeln!(self.output, "{line}")?;
+            }
+            let Some(solution) = solution else {
+                break;
+            };
+            let bindings: Vec<String> = solution
+                .bindings
+                .iter()
+                .map(|theta| theta.to_string())
+                .collect();
+            let text = if bindings.is_empty() {
+                "命题为真".to_string()
+            } else {
+                bindings.join(", ")
+            };
+            write!(self.output, "{text} ")?;
+            self.output.flush()?;
+            let mut next = String::new();
+            self.input.read_line(&mut next)?;
+            if next.trim() != ";" {
+                exhausted = false;
+                break;
+            }
+        }
+        solutions.trace_steps();
+        for line in solutions.take_trace() {
+            writeln!(self.output, "{line}")?;
+        }
+        let summary = solutions.summary();
+        // 否定中含有未确定的变量时放宽限制无济于事，优先报告
+        let incomplete = if summary.floundered {
+            Some(ReasoningError::Floundering)
+        } else {
+            summary.limit.map(ReasoningError::from)
+        };
+        match (summary.solutions, incomplete) {
+            (0, None) => writeln!(self.output, "无法证明命题为真")?,
+            (0, Some(reason)) => writeln!(self.output, "无法证明命题为真：{reason}")?,
+            (_, None) if exhausted => writeln!(self.output, "没有更多的解")?,
+            (_, Some(reason)) if exhausted => {
+                writeln!(self.output, "{reason}，可能还有未找到的解")?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(kb: &str, input: &str) -> String {
+        let kb = parse_kb(kb).unwrap();
+        let mut output = Vec::<u8>::new();
+        Repl::new(kb, input.as_bytes(), &mut output).run().unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_repl_query() {
+        let output = session(
+            "parent(tom, bob). parent(tom, liz).",
+            "parent(tom, X)\n;\n;\nparent(bob, X)\n",
+        );
+        assert_eq!(
+            output,
+            "?- X = bob X = liz 没有更多的解\n?- 无法证明命题为真\n?- \n"
+        );
+    }
+
+    #[test]
+    fn test_repl_commands() {
+        let output = session(
+            "p(a).",
+            "assert q(X) :- p(X).\nassert q(a, b).\n:listing\nq(Y)\n\nretract 0\nq(Y)\n:depth x\n:quit\np(a)\n",
+        );
+        assert_eq!(
+            output,
+            "?- 已添加1条规则\n\
+             ?- 参数个数不一致：第1条规则中为q/1，第2条规则中为q/2\n\
+             ?- #0 p(a).\n#1 q(X) :- p(X).\n\
+             ?- Y = a ?- 已删除规则 p(a).\n\
+             ?- 无法证明命题为真\n\
+             ?- 用法：:depth <N>，当前为5\n\
+             ?- "
+        );
+    }
+
+    #[test]
+    fn test_repl_timeout() {
+        // 过长的时间上限视为不限时，无法表示的秒数被拒绝
+        let output = session(
+            "p(a).",
+            ":timeout 1e19\np(a)\n\n:timeout 1e20\n:timeout -1\n",
+        );
+        assert_eq!(
+            output,
+            "?- ?- 命题为真 ?- 用法：:timeout <秒>|off\n?- 用法：:timeout <秒>|off\n?- \n"
+        );
+    }
+
+    #[test]
+    fn test_repl_trace() {
+        // 证明过程写入会话的输出，而非标准输出
+        let output = session(
+            "p(a). q(X) :- p(X), \\+ r(X).",
+            ":trace on\nq(a)\n\n:trace off\nq(a)\n\n",
+        );
+        assert_eq!(
+            output,
+            "?- ?- 对q(a)的证明：\n对\\+ r(a)的证明：\n对r(a)的证明：\nq(a)得到了证明\n\
+             命题为真 证明步数：2\n\
+             ?- ?- 命题为真 ?- \n"
+        );
+    }
+}
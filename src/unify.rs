@@ -1,21 +1,27 @@
-use crate::{Atom, ReasoningError, Symbol, Theta, func};
+use crate::bindings::Bindings;
+use crate::{Atom, ReasoningError, Symbol, Variable};
 
 /// 合一项
-fn unify_symbol(x: &Symbol, y: &Symbol, theta_list: &mut Vec<Theta>) -> Result<(), ReasoningError> {
+fn unify_symbol(
+    x: &Symbol,
+    y: &Symbol,
+    bindings: &mut Bindings,
+    occurs_check: bool,
+) -> Result<(), ReasoningError> {
     if x == y {
         return Ok(());
     } else if let Symbol::Var(_) = x {
-        return unify_var(x, y, theta_list);
+        return unify_var(x, y, bindings, occurs_check);
     } else if let Symbol::Var(_) = y {
-        return unify_var(y, x, theta_list);
+        return unify_var(y, x, bindings, occurs_check);
     } else if let Symbol::Func(x_name, x_args) = x
         && let Symbol::Func(y_name, y_args) = y
     {
-        if x_name != y_name {
+        if x_name != y_name || x_args.len() != y_args.len() {
             return Err(ReasoningError::UnifyError);
         } else {
             for (x_arg, y_arg) in x_args.iter().zip(y_args.iter()) {
-                unify_symbol(x_arg, y_arg, theta_list)?;
+                unify_symbol(x_arg, y_arg, bindings, occurs_check)?;
             }
             return Ok(());
         }
@@ -23,82 +29,170 @@ fn unify_symbol(x: &Symbol, y: &Symbol, theta_list: &mut Vec<Theta>) -> Result<(
     Err(ReasoningError::UnifyError)
 }
 
-/// 合一谓词
-pub fn unify(x: &Atom, y: &Atom, theta_list: &mut Vec<Theta>) -> Result<(), ReasoningError> {
-    if x.predicate == y.predicate {
-        for (x_arg, y_arg) in x.args.iter().zip(y.args.iter()) {
-            unify_symbol(x_arg, y_arg, theta_list)?;
+/// ## 合一谓词
+/// 谓词与函数符号均由名称与参数个数共同确定，参数个数不同的无法合一。
+/// 合一成功时新的绑定记入绑定表，失败时撤销本次合一中做出的所有绑定。
+/// `occurs_check`为真时拒绝将变量替换为含有该变量自身的项（如`X = f(X)`），保证合一的正确性；
+/// 关闭后合一更快，但可能产生循环的绑定，使`exhaust_subst`无法终止
+pub fn unify(
+    x: &Atom,
+    y: &Atom,
+    bindings: &mut Bindings,
+    occurs_check: bool,
+) -> Result<(), ReasoningError> {
+    if x.predicate != y.predicate || x.args.len() != y.args.len() {
+        return Err(ReasoningError::UnifyError);
+    }
+    let mark = bindings.mark();
+    for (x_arg, y_arg) in x.args.iter().zip(y.args.iter()) {
+        if let Err(e) = unify_symbol(x_arg, y_arg, bindings, occurs_check) {
+            bindings.undo(mark);
+            return Err(e);
         }
-        return Ok(());
     }
-    Err(ReasoningError::UnifyError)
+    Ok(())
 }
 
 /// 单变量合一
-fn unify_var(var: &Symbol, x: &Symbol, theta_list: &mut Vec<Theta>) -> Result<(), ReasoningError> {
-    if let Some(val) = subst_known(var, theta_list) {
-        unify_symbol(&val, x, theta_list)?;
-    } else if let Some(val) = subst_known(x, theta_list) {
-        unify_symbol(var, &val, theta_list)?;
-    } else {
-        theta_list.push(Theta::new(var.clone(), x.clone()).unwrap());
+fn unify_var(
+    var: &Symbol,
+    x: &Symbol,
+    bindings: &mut Bindings,
+    occurs_check: bool,
+) -> Result<(), ReasoningError> {
+    if let Some(val) = subst_known(var, bindings) {
+        unify_symbol(&val.clone(), x, bindings, occurs_check)?;
+    } else if let Some(val) = subst_known(x, bindings) {
+        unify_symbol(var, &val.clone(), bindings, occurs_check)?;
+    } else if occurs_check && occurs(var, x, bindings) {
+        return Err(ReasoningError::UnifyError);
+    } else if let (Symbol::Var(v), Symbol::Var(w)) = (var, x)
+        && w.id > v.id
+    {
+        // 两个未绑定的变量合一时将编号较大（较晚改名得到）的变量绑定到较早的变量，
+        // 避免递归调用逐层产生越来越长的绑定链
+        bindings.bind(*w, var.clone());
+    } else if let Symbol::Var(v) = var {
+        bindings.bind(*v, x.clone());
     }
     Ok(())
 }
 
-/// 在已知的置换列表中找到一个变量x的置换结果
-fn subst_known(x: &Symbol, theta_list: &[Theta]) -> Option<Symbol> {
-    if let Symbol::Var(name) = x {
-        let theta_iter = theta_list.iter();
-        for theta in theta_iter {
-            match theta.origin {
-                Symbol::Var(ref existed_name) if existed_name == name => {
-                    return Some(theta.result.clone());
-                }
-                _ => {}
-            }
+/// 判断变量var在已知绑定下是否出现在项x中
+fn occurs(var: &Symbol, x: &Symbol, bindings: &Bindings) -> bool {
+    match x {
+        Symbol::Var(_) => {
+            x == var || subst_known(x, bindings).is_some_and(|val| occurs(var, val, bindings))
         }
+        Symbol::Val(_) => false,
+        Symbol::Func(_, args) => args.iter().any(|arg| occurs(var, arg, bindings)),
+    }
+}
+
+/// 在绑定表中找到变量x绑定的项
+fn subst_known<'a>(x: &Symbol, bindings: &'a Bindings) -> Option<&'a Symbol> {
+    match x {
+        Symbol::Var(v) => bindings.get(v),
+        _ => None,
     }
-    None
 }
 
-/// 使用已知的置换列表反复作用于项x直至无法再被置换
-fn exhaust_subst_symbol(x: &Symbol, theta_list: &[Theta]) -> Symbol {
+/// 使用绑定表反复作用于项x直至无法再被置换
+pub fn exhaust_subst_symbol(x: &Symbol, bindings: &Bindings) -> Symbol {
     match x {
         Symbol::Var(_) => {
-            if let Some(new_x) = subst_known(x, theta_list) {
-                exhaust_subst_symbol(&new_x, theta_list)
+            if let Some(new_x) = subst_known(x, bindings) {
+                exhaust_subst_symbol(new_x, bindings)
             } else {
                 x.clone()
             }
         }
         Symbol::Val(_) => x.clone(),
-        Symbol::Func(name, args) => {
-            let mut new_args = Vec::<Symbol>::new();
-            for arg in args.iter() {
-                new_args.push(exhaust_subst_symbol(arg, theta_list));
+        Symbol::Func(name, args) => match subst_args(args, bindings) {
+            Some(new_args) => Symbol::Func(*name, new_args.into()),
+            None => x.clone(),
+        },
+    }
+}
+
+/// 置换函数符号的参数，所有参数均未改变时返回None，以便直接共享原有的参数列表
+fn subst_args(args: &[Symbol], bindings: &Bindings) -> Option<Vec<Symbol>> {
+    let mut new_args: Option<Vec<Symbol>> = None;
+    for (i, arg) in args.iter().enumerate() {
+        let new_arg = exhaust_subst_symbol(arg, bindings);
+        match &mut new_args {
+            Some(new_args) => new_args.push(new_arg),
+            None if new_arg != *arg => {
+                let mut changed = args[..i].to_vec();
+                changed.push(new_arg);
+                new_args = Some(changed);
             }
-            func(name.clone(), new_args)
+            None => {}
         }
     }
+    new_args
 }
 
-/// 使用已知的置换列表反复作用于项x直至无法再被置换
-pub fn exhaust_subst(x: &Atom, theta_list: &[Theta]) -> Atom {
+/// 使用绑定表反复作用于原子公式x直至无法再被置换
+pub fn exhaust_subst(x: &Atom, bindings: &Bindings) -> Atom {
     Atom {
-        predicate: x.predicate.clone(),
+        predicate: x.predicate,
         args: x
             .args
             .iter()
-            .map(|arg| exhaust_subst_symbol(arg, theta_list))
+            .map(|arg| exhaust_subst_symbol(arg, bindings))
             .collect(),
     }
 }
 
+/// 判断两项是否互为变体，`renaming`记录已建立的变量一一对应关系
+fn variant_symbol<'a>(
+    x: &'a Symbol,
+    y: &'a Symbol,
+    renaming: &mut Vec<(&'a Variable, &'a Variable)>,
+) -> bool {
+    match (x, y) {
+        (Symbol::Var(x_name), Symbol::Var(y_name)) => {
+            match renaming
+                .iter()
+                .find(|(from, to)| *from == x_name || *to == y_name)
+            {
+                Some((from, to)) => *from == x_name && *to == y_name,
+                None => {
+                    renaming.push((x_name, y_name));
+                    true
+                }
+            }
+        }
+        (Symbol::Val(x_name), Symbol::Val(y_name)) => x_name == y_name,
+        (Symbol::Func(x_name, x_args), Symbol::Func(y_name, y_args)) => {
+            x_name == y_name
+                && x_args.len() == y_args.len()
+                && x_args
+                    .iter()
+                    .zip(y_args.iter())
+                    .all(|(x_arg, y_arg)| variant_symbol(x_arg, y_arg, renaming))
+        }
+        _ => false,
+    }
+}
+
+/// ## 判断两条原子公式是否互为变体
+/// 即二者仅在变量命名上有所不同，如`leq(X, add(Y, zero))`与`leq(A, add(B, zero))`
+pub fn is_variant(x: &Atom, y: &Atom) -> bool {
+    let mut renaming = Vec::<(&Variable, &Variable)>::new();
+    x.predicate == y.predicate
+        && x.args.len() == y.args.len()
+        && x.args
+            .iter()
+            .zip(y.args.iter())
+            .all(|(x_arg, y_arg)| variant_symbol(x_arg, y_arg, &mut renaming))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{func, val, var};
+    use crate::{func, pred, val, var};
     #[test]
     fn test_unify_sym() {
         let a = func("add", vec![val("zero"), var("x")]);
@@ -106,11 +200,102 @@ mod tests {
             "add",
             vec![var("y"), func("add", vec![var("zero"), var("zero")])],
         );
-        let mut thetas = Vec::<Theta>::new();
-        unify_symbol(&a, &b, &mut thetas).unwrap();
+        let mut bindings = Bindings::new();
+        unify_symbol(&a, &b, &mut bindings, true).unwrap();
+        assert_eq!(
+            exhaust_subst_symbol(&a, &bindings),
+            exhaust_subst_symbol(&b, &bindings)
+        );
+    }
+
+    #[test]
+    fn test_subst_shares_ground_args() {
+        let ground = func("s", vec![val("zero")]);
+        let x = func("add", vec![ground.clone(), var("x")]);
+        let mut bindings = Bindings::new();
+        unify_symbol(&var("x"), &val("zero"), &mut bindings, true).unwrap();
+        let Symbol::Func(_, args) = exhaust_subst_symbol(&x, &bindings) else {
+            unreachable!()
+        };
+        let (Symbol::Func(_, new), Symbol::Func(_, old)) = (&args[0], &ground) else {
+            unreachable!()
+        };
+        assert_eq!(new.id(), old.id());
+        assert_eq!(args[1], val("zero"));
+    }
+
+    #[test]
+    fn test_occurs_check() {
+        let x = var("x");
+        let fx = func("f", vec![var("x")]);
+        let mut bindings = Bindings::new();
+        assert!(unify_symbol(&x, &fx, &mut bindings, true).is_err());
+        assert_eq!(bindings.mark(), 0);
+        // X = f(Y), Y = g(X)构成循环
+        let mut bindings = Bindings::new();
+        unify_symbol(&x, &func("f", vec![var("y")]), &mut bindings, true).unwrap();
+        let gx = func("g", vec![var("x")]);
+        assert!(unify_symbol(&var("y"), &gx, &mut bindings, true).is_err());
+        // p(X, X)与p(Y, f(Y))无法合一
+        let a = pred("p", vec![var("x"), var("x")]);
+        let b = pred("p", vec![var("y"), func("f", vec![var("y")])]);
+        let mut bindings = Bindings::new();
+        assert!(unify(&a, &b, &mut bindings, true).is_err());
+        let mut bindings = Bindings::new();
+        assert!(unify(&a, &b, &mut bindings, false).is_ok());
+        // 不构成循环的绑定链不受影响
+        let mut bindings = Bindings::new();
+        unify_symbol(&x, &var("y"), &mut bindings, true).unwrap();
+        unify_symbol(&var("y"), &func("f", vec![var("z")]), &mut bindings, true).unwrap();
         assert_eq!(
-            exhaust_subst_symbol(&a, &thetas),
-            exhaust_subst_symbol(&b, &thetas)
+            exhaust_subst_symbol(&x, &bindings),
+            func("f", vec![var("z")])
+        );
+    }
+
+    #[test]
+    fn test_bind_younger_var() {
+        let old = pred("p", vec![var("x")]);
+        let young = old.rename_apart(&mut 0);
+        let mut bindings = Bindings::new();
+        unify(&old, &young, &mut bindings, true).unwrap();
+        assert_eq!(exhaust_subst(&young, &bindings), old);
+        assert_eq!(exhaust_subst(&old, &bindings), old);
+    }
+
+    #[test]
+    fn test_unify_arity() {
+        let mut bindings = Bindings::new();
+        let p1 = pred("p", vec![val("a")]);
+        let p2 = pred("p", vec![val("a"), val("b")]);
+        assert!(unify(&p1, &p2, &mut bindings, true).is_err());
+        let add1 = func("add", vec![var("x")]);
+        let add2 = func("add", vec![var("x"), var("y")]);
+        assert!(unify_symbol(&add1, &add2, &mut bindings, true).is_err());
+        assert_eq!(bindings.mark(), 0);
+    }
+
+    #[test]
+    fn test_is_variant() {
+        let a = pred(
+            "leq",
+            vec![var("x"), func("add", vec![var("y"), val("zero")])],
+        );
+        let b = pred(
+            "leq",
+            vec![var("u"), func("add", vec![var("v"), val("zero")])],
+        );
+        assert!(is_variant(&a, &b));
+        let c = pred(
+            "leq",
+            vec![var("u"), func("add", vec![var("u"), val("zero")])],
+        );
+        assert!(!is_variant(&a, &c));
+        assert!(!is_variant(&c, &a));
+        let d = pred(
+            "leq",
+            vec![val("x"), func("add", vec![var("y"), val("zero")])],
         );
+        assert!(!is_variant(&a, &d));
     }
 }
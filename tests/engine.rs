@@ -0,0 +1,259 @@
+//! 以下游程序的方式使用推理引擎
+
+use reasoning::{
+    Atom, Engine, KB, Limit, Outcome, ReasoningError, Rule, Strategy, Symbol, func, not, pred, val,
+    var,
+};
+
+#[test]
+fn prove_from_json_file() {
+    let kb = KB::load("examples/AIMA/knowledge_base.json").unwrap();
+    let engine = Engine::new(kb).unwrap();
+    let answer = engine.prove(&pred("criminal", vec![var("x")])).unwrap();
+    assert_eq!(answer.len(), 1);
+    assert_eq!(answer[0].origin(), &var("x"));
+    assert_eq!(answer[0].result(), &val("west"));
+    assert_eq!(answer[0].to_string(), "X = west");
+}
+
+#[test]
+fn enumerate_solutions_of_kb_built_in_code() {
+    let mut kb = KB::new(vec![
+        Rule {
+            condition: vec![],
+            conclusion: pred("parent", vec![val("tom"), val("bob")]),
+        },
+        Rule {
+            condition: vec![],
+            conclusion: pred("parent", vec![val("bob"), val("ann")]),
+        },
+        Rule {
+            condition: vec![],
+            conclusion: pred("parent", vec![val("bob"), val("pat")]),
+        },
+    ]);
+    kb.add_rule(Rule {
+        condition: vec![pred("parent", vec![var("x"), var("y")]).into()],
+        conclusion: pred("ancestor", vec![var("x"), var("y")]),
+    });
+    kb.add_rule(Rule {
+        condition: vec![
+            pred("parent", vec![var("x"), var("y")]).into(),
+            pred("ancestor", vec![var("y"), var("z")]).into(),
+        ],
+        conclusion: pred("ancestor", vec![var("x"), var("z")]),
+    });
+    let engine = Engine::builder().max_depth(10).build(kb).unwrap();
+    let goal = pred("ancestor", vec![val("tom"), var("who")]);
+    let mut solutions = engine.solutions(&goal);
+    let first = solutions.next().unwrap();
+    assert_eq!(first.bindings[0].to_string(), "WHO = bob");
+    assert!(first.proof.is_none());
+    assert_eq!(solutions.summary().solutions, 1);
+    let rest: Vec<String> = solutions
+        .by_ref()
+        .map(|solution| solution.bindings[0].to_string())
+        .collect();
+    assert_eq!(rest, vec!["WHO = ann", "WHO = pat"]);
+    let summary = solutions.summary();
+    assert_eq!(summary.solutions, 3);
+    assert_eq!(summary.outcome, Outcome::Proved);
+    assert_eq!(summary.limit, None);
+}
+
+#[test]
+fn step_budget_and_strategy() {
+    let kb = KB::load("examples/math/math.pl").unwrap();
+    let goal = Atom::parse("leq(seven, add(three, nine))").unwrap();
+    let engine = Engine::builder()
+        .strategy(Strategy::IterativeDeepening)
+        .max_steps(20000)
+        .build(kb.clone())
+        .unwrap();
+    assert_eq!(engine.config().max_depth, usize::MAX);
+    assert!(engine.prove(&goal).unwrap().is_empty());
+    let engine = Engine::builder().max_steps(10).build(kb).unwrap();
+    assert!(matches!(
+        engine.prove(&goal),
+        Err(ReasoningError::StepLimitExceed)
+    ));
+    let mut solutions = engine.solutions(&goal);
+    assert!(solutions.next().is_none());
+    assert_eq!(solutions.summary().outcome, Outcome::Unknown(Limit::Steps));
+}
+
+#[test]
+fn proof_tree_and_terms() {
+    let kb = KB::parse("nat(zero). nat(s(X)) :- nat(X).").unwrap();
+    let engine = Engine::new(kb).unwrap();
+    let goal = pred("nat", vec![func("s", vec![func("s", vec![val("zero")])])]);
+    assert_eq!(goal.predicate(), "nat");
+    let Symbol::Func(name, args) = &goal.args()[0] else {
+        panic!("参数应为函数符号");
+    };
+    assert_eq!(name.as_str(), "s");
+    assert_eq!(args.len(), 1);
+    let (answer, tree) = engine.prove_with_tree(&goal).unwrap();
+    assert!(answer.is_empty());
+    assert_eq!(tree.goal(), &goal);
+    assert_eq!(tree.rule(), Some(1));
+    assert_eq!(
+        tree.children()[0].children()[0].goal().to_string(),
+        "nat(zero)"
+    );
+    assert!(tree.to_dot(engine.kb()).starts_with("digraph proof"));
+    let proofs: Vec<_> = engine.proofs(&goal).collect();
+    assert_eq!(proofs.len(), 1);
+    assert!(proofs[0].proof.is_some());
+}
+
+#[test]
+fn tabled_predicate_declared_in_code() {
+    let mut kb = KB::parse(
+        "path(X, Z) :- path(X, Y), edge(Y, Z).
+         path(X, Y) :- edge(X, Y).
+         edge(a, b). edge(b, c). edge(c, a).",
+    )
+    .unwrap();
+    kb.table("path", 2);
+    let engine = Engine::builder().max_depth(50).build(kb).unwrap();
+    let mut answers: Vec<String> = engine
+        .solutions(&Atom::parse("path(a, X)").unwrap())
+        .map(|solution| solution.bindings[0].to_string())
+        .collect();
+    answers.sort();
+    assert_eq!(answers, vec!["X = a", "X = b", "X = c"]);
+}
+
+#[test]
+fn arity_mismatch_is_rejected() {
+    let kb = KB::parse("p(a). p(a, b).").unwrap();
+    assert!(matches!(
+        Engine::new(kb),
+        Err(ReasoningError::ArityMismatch(_))
+    ));
+}
+
+#[test]
+fn saturate_agrees_with_backward_chaining() {
+    let kb = KB::load("examples/AIMA/knowledge_base.json").unwrap();
+    let engine = Engine::builder().max_depth(10).build(kb).unwrap();
+    let saturation = engine.saturate(10, usize::MAX);
+    assert!(saturation.fixpoint);
+    assert!(
+        saturation
+            .derived
+            .contains(&Atom::parse("criminal(west)").unwrap())
+    );
+    for fact in &saturation.derived {
+        assert!(engine.prove(fact).is_ok(), "{fact}");
+    }
+}
+
+#[test]
+fn datalog_strategy_matches_backward_chaining() {
+    let kb = KB::load("examples/AIMA/knowledge_base.json").unwrap();
+    let datalog = Engine::builder()
+        .strategy(Strategy::Datalog)
+        .build(kb.clone())
+        .unwrap();
+    let depth_first = Engine::builder().max_depth(10).build(kb).unwrap();
+    for goal in [
+        "criminal(X)",
+        "sells(X, Y, Z)",
+        "weapon(X)",
+        "hostile(west)",
+    ] {
+        let goal = Atom::parse(goal).unwrap();
+        let expected: Vec<_> = depth_first.solutions(&goal).map(|s| s.bindings).collect();
+        let mut solutions = datalog.solutions(&goal);
+        let found: Vec<_> = solutions.by_ref().map(|s| s.bindings).collect();
+        assert_eq!(found, expected);
+        assert_eq!(solutions.summary().limit, None);
+    }
+    // 匿名变量的绑定不予给出，与反向链接一致
+    let goal = Atom::parse("sells(_, X, nono)").unwrap();
+    let answer = datalog.prove(&goal).unwrap();
+    assert_eq!(answer, depth_first.prove(&goal).unwrap());
+    assert_eq!(answer.len(), 1);
+    assert_eq!(answer[0].to_string(), "X = m1");
+    // 含函数符号的知识库仍使用反向链接
+    let math = KB::load("examples/math/math.pl").unwrap();
+    let engine = Engine::builder()
+        .strategy(Strategy::Datalog)
+        .build(math)
+        .unwrap();
+    assert!(
+        engine
+            .prove(&Atom::parse("leq(seven, add(three, nine))").unwrap())
+            .is_ok()
+    );
+}
+
+#[test]
+fn negation_as_failure() {
+    let mut kb = KB::load("examples/AIMA/knowledge_base.json").unwrap();
+    // 与美国结盟的敌国不视为敌对
+    kb.add_rule(Rule {
+        condition: vec![
+            pred("enemy", vec![var("x"), val("america")]).into(),
+            not(pred("allied", vec![var("x"), val("america")])),
+        ],
+        conclusion: pred("unfriendly", vec![var("x")]),
+    });
+    kb.add_rule(Rule {
+        condition: vec![],
+        conclusion: pred("enemy", vec![val("wakanda"), val("america")]),
+    });
+    kb.add_rule(Rule {
+        condition: vec![],
+        conclusion: pred("allied", vec![val("wakanda"), val("america")]),
+    });
+    let goal = Atom::parse("unfriendly(X)").unwrap();
+    for strategy in [
+        Strategy::DepthFirst,
+        Strategy::IterativeDeepening,
+        Strategy::Datalog,
+    ] {
+        let engine = Engine::builder()
+            .strategy(strategy)
+            .max_depth(10)
+            .build(kb.clone())
+            .unwrap();
+        let found: Vec<String> = engine
+            .solutions(&goal)
+            .map(|s| s.bindings[0].to_string())
+            .collect();
+        assert_eq!(found, ["X = nono"], "{strategy:?}");
+        assert!(matches!(
+            engine.prove(&Atom::parse("unfriendly(wakanda)").unwrap()),
+            Err(ReasoningError::ProofNotFound)
+        ));
+    }
+    let engine = Engine::new(kb.clone()).unwrap();
+    assert!(
+        engine
+            .saturate(10, usize::MAX)
+            .derived
+            .contains(&Atom::parse("unfriendly(nono)").unwrap())
+    );
+    // 否定中含有未确定的变量
+    let mut floundering = kb.clone();
+    floundering.add_rule(Rule {
+        condition: vec![not(pred("allied", vec![var("x"), val("america")]))],
+        conclusion: pred("neutral", vec![var("x")]),
+    });
+    let engine = Engine::new(floundering).unwrap();
+    let mut solutions = engine.solutions(&Atom::parse("neutral(X)").unwrap());
+    assert!(solutions.next().is_none());
+    assert_eq!(solutions.summary().outcome, Outcome::Floundered);
+    // 经由否定的递归无法分层
+    kb.add_rule(Rule {
+        condition: vec![not(pred("unfriendly", vec![var("x")]))],
+        conclusion: pred("enemy", vec![var("x"), val("america")]),
+    });
+    assert!(matches!(
+        Engine::new(kb),
+        Err(ReasoningError::Unstratified(_))
+    ));
+}